@@ -1,7 +1,11 @@
 #![no_std]
-use soroban_sdk::{contract, contractimpl, contracttype, contracterror, contractclient, Env, Vec, String, Address, BytesN, Map, vec};
+use soroban_sdk::{contract, contractevent, contractimpl, contracttype, contracterror, contractclient, Env, Vec, String, Address, BytesN, Map, vec};
+
+mod oracle_client;
+pub use oracle_client::{OrderBookData, OrderBookEntry};
 
 #[contracttype]
+#[derive(Clone)]
 pub struct ArbitrageOpportunity {
     pub asset: String,
     pub buy_exchange: String,
@@ -11,9 +15,35 @@ pub struct ArbitrageOpportunity {
     pub available_amount: i128,
     pub estimated_profit: i128,
     pub confidence_score: i128,
+    pub estimated_slippage_bps: i128,
+    pub expiry_time: u64,
+}
+
+// Sell-high-first opportunity: inventory already held is sold on the dearer venue, then
+// bought back on the cheaper one, so no capital or flash loan is needed
+#[contracttype]
+pub struct SellFirstOpportunity {
+    pub asset: String,
+    pub sell_exchange: String,
+    pub buy_exchange: String,
+    pub sell_price: i128,
+    pub buy_price: i128,
+    pub inventory_amount: i128,
+    pub estimated_profit: i128,
     pub expiry_time: u64,
 }
 
+// Published for an opportunity that missed min_profit by no more than the near-miss margin
+#[contractevent(data_format = "vec")]
+pub struct NearMiss {
+    #[topic]
+    pub asset: String,
+    pub buy_exchange: String,
+    pub sell_exchange: String,
+    pub profit: i128,
+    pub min_profit: i128,
+}
+
 // Real asset registry for Reflector Oracle tracked assets
 #[contracttype]
 #[derive(Clone)]
@@ -41,6 +71,26 @@ pub enum ArbitrageError {
     InvalidAsset = 2,
     NoOpportunityFound = 3,
     InvalidContractId = 4,
+    AlreadyInitialized = 5,
+    NotInitialized = 6,
+    UnknownExchange = 7,
+    ExchangeCallFailed = 8,
+    InsufficientLiquidity = 9,
+    InvalidSide = 10,
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub enum DataKey {
+    Admin,
+    MinSpreadBps,
+    MinLiquidity,
+    NearMissMargin,
+    OpportunityTtl,
+    Exchanges,
+    ExchangeAdapter(String),
+    AssetMinConfidence(String),
+    ScanExchanges,
 }
 
 // Reflector Network contract client interface
@@ -50,11 +100,89 @@ pub trait ReflectorOracleInterface {
     fn get_supported_assets() -> Result<Vec<String>, ArbitrageError>;
 }
 
+// Rates passed to implied_rate carry 7 decimals, like Stellar asset amounts
+const RATE_SCALE: i128 = 10_000_000;
+
+// Combined taker fees for the buy and sell legs, in basis points
+const TOTAL_FEE_BPS: i128 = 60;
+
+// Confidence assigned to an opportunity before any slippage penalty
+const BASE_CONFIDENCE: i128 = 95;
+
+// Basis points of expected buy-side slippage that cost one point of confidence
+const SLIPPAGE_BPS_PER_CONFIDENCE_POINT: i128 = 10;
+
+// How far, in basis points, a spread may shrink below the estimated profit and still be executed
+const REVALIDATION_TOLERANCE_BPS: i128 = 1000;
+
+// Default lifetime of a detected opportunity, in seconds
+const DEFAULT_OPPORTUNITY_TTL_SECONDS: u64 = 30;
+
+// Which leg of an arbitrage runs first: buying on the cheaper venue, or selling held inventory
+// on the dearer one
+#[derive(Clone, Copy, PartialEq)]
+enum LegOrder {
+    BuyFirst,
+    SellFirst,
+}
+
+// Exchange adapter contract interface, one adapter registered per venue
+#[contractclient(name = "ExchangeClient")]
+pub trait ExchangeInterface {
+    fn get_order_book(asset_code: String) -> OrderBookData;
+}
+
 #[contract]
 pub struct ArbitrageDetector;
 
 #[contractimpl]
 impl ArbitrageDetector {
+    /// Initialize the detector with an admin allowed to tune scan settings
+    pub fn initialize(env: Env, admin: Address) -> Result<(), ArbitrageError> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(ArbitrageError::AlreadyInitialized);
+        }
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        Ok(())
+    }
+
+    /// Set the minimum spread, in basis points of the buy price, an opportunity must offer (admin only)
+    pub fn set_min_spread_bps(env: Env, min_spread_bps: i128) -> Result<(), ArbitrageError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::MinSpreadBps, &min_spread_bps);
+        Ok(())
+    }
+
+    /// Get the minimum spread in basis points (0 when unset)
+    pub fn get_min_spread_bps(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::MinSpreadBps).unwrap_or(0)
+    }
+
+    /// Set the ask depth the buy venue must hold at or below the buy price; 0 disables the check (admin only)
+    pub fn set_min_liquidity(env: Env, min_liquidity: i128) -> Result<(), ArbitrageError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::MinLiquidity, &min_liquidity);
+        Ok(())
+    }
+
+    /// Get the minimum buy-side depth (0 when unset)
+    pub fn get_min_liquidity(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::MinLiquidity).unwrap_or(0)
+    }
+
+    /// Mark an asset as high risk, requiring its opportunities to score at least `min_confidence`;
+    /// 0 returns it to the normal treatment (admin only)
+    pub fn set_high_risk_asset(env: Env, asset: String, min_confidence: i128) -> Result<(), ArbitrageError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::AssetMinConfidence(asset), &min_confidence);
+        Ok(())
+    }
+
+    /// Get the confidence floor for an asset's opportunities (0 unless marked high risk)
+    pub fn get_asset_min_confidence(env: Env, asset: String) -> i128 {
+        env.storage().instance().get(&DataKey::AssetMinConfidence(asset)).unwrap_or(0)
+    }
+
     /// Returns the list of supported real assets
     pub fn get_supported_assets(env: Env) -> Vec<RealAsset> {
         let mut assets = Vec::new(&env);
@@ -92,86 +220,391 @@ impl ArbitrageDetector {
         assets
     }
     
+    /// Set how far below `min_profit` a rejected opportunity may fall and still be
+    /// published as a near-miss event for threshold tuning; 0 disables it (admin only)
+    pub fn set_near_miss_margin(env: Env, margin: i128) -> Result<(), ArbitrageError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::NearMissMargin, &margin);
+        Ok(())
+    }
+
+    /// Set how long detected opportunities stay valid, in seconds (admin only)
+    pub fn set_opportunity_ttl(env: Env, opportunity_ttl_seconds: u64) -> Result<(), ArbitrageError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::OpportunityTtl, &opportunity_ttl_seconds);
+        Ok(())
+    }
+
+    /// Get the opportunity lifetime in seconds
+    pub fn get_opportunity_ttl(env: Env) -> u64 {
+        env.storage().instance().get(&DataKey::OpportunityTtl).unwrap_or(DEFAULT_OPPORTUNITY_TTL_SECONDS)
+    }
+
+    /// Register the adapter contract serving an exchange's order books (admin only)
+    pub fn register_exchange(env: Env, exchange: String, adapter: Address) -> Result<(), ArbitrageError> {
+        Self::require_admin(&env)?;
+
+        let mut exchanges: Vec<String> = env.storage().instance().get(&DataKey::Exchanges).unwrap_or(Vec::new(&env));
+        if !exchanges.contains(&exchange) {
+            exchanges.push_back(exchange.clone());
+            env.storage().instance().set(&DataKey::Exchanges, &exchanges);
+        }
+
+        env.storage().instance().set(&DataKey::ExchangeAdapter(exchange), &adapter);
+        Ok(())
+    }
+
+    /// Select which exchanges each asset is compared across during scans: registered exchanges,
+    /// quoted from their adapter's book, or the built-in simulated venues. Names with no quote
+    /// source are rejected and repeated ones kept once (admin only).
+    pub fn set_scan_exchanges(env: Env, exchanges: Vec<String>) -> Result<(), ArbitrageError> {
+        Self::require_admin(&env)?;
+
+        let registered = Self::list_exchanges(env.clone());
+        let mut selected = Vec::new(&env);
+        for exchange in exchanges.iter() {
+            if !registered.contains(&exchange) && Self::simulated_quote(&env, &exchange, 0).is_none() {
+                return Err(ArbitrageError::UnknownExchange);
+            }
+            if !selected.contains(&exchange) {
+                selected.push_back(exchange);
+            }
+        }
+
+        env.storage().instance().set(&DataKey::ScanExchanges, &selected);
+        Ok(())
+    }
+
+    /// Get the venues compared during scans (Stellar DEX, Soroswap and Aqua Network when unset)
+    pub fn get_scan_exchanges(env: Env) -> Vec<String> {
+        env.storage().instance().get(&DataKey::ScanExchanges).unwrap_or(vec![
+            &env,
+            String::from_str(&env, "Stellar DEX"),
+            String::from_str(&env, "Soroswap"),
+            String::from_str(&env, "Aqua Network"),
+        ])
+    }
+
+    /// Names of the registered exchanges, in registration order
+    pub fn list_exchanges(env: Env) -> Vec<String> {
+        env.storage().instance().get(&DataKey::Exchanges).unwrap_or(Vec::new(&env))
+    }
+
+    /// Codes of the supported assets
+    pub fn list_assets(env: Env) -> Vec<String> {
+        let mut codes = Vec::new(&env);
+        for asset in Self::get_supported_assets(env.clone()).iter() {
+            codes.push_back(asset.code);
+        }
+        codes
+    }
+
+    /// Total order book depth for an asset, bids and asks, across every registered exchange.
+    /// Exchanges whose adapter call fails are left out of the total.
+    pub fn get_aggregate_liquidity(env: Env, asset: String) -> i128 {
+        let exchanges = Self::list_exchanges(env.clone());
+
+        let mut total = 0;
+        for exchange in exchanges.iter() {
+            if let Ok(book) = Self::get_order_book(&env, &exchange, &asset) {
+                for entry in book.bids.iter().chain(book.asks.iter()) {
+                    total += entry.amount;
+                }
+            }
+        }
+        total
+    }
+
+    /// Split a buy of `total_amount` across two venues, always filling from the cheaper ask.
+    /// Fails with `InsufficientLiquidity` when both books together can't fill the amount.
+    pub fn compute_optimal_split(env: Env, asset: String, venue_a: String, venue_b: String, total_amount: i128) -> Result<(i128, i128), ArbitrageError> {
+        let asks_a = Self::get_order_book(&env, &venue_a, &asset)?.asks;
+        let asks_b = Self::get_order_book(&env, &venue_b, &asset)?.asks;
+
+        let mut remaining = total_amount;
+        let (mut amount_a, mut amount_b) = (0i128, 0i128);
+        let (mut i, mut j) = (0u32, 0u32);
+
+        // Asks are ordered best price first on both books
+        while remaining > 0 {
+            let (level, from_a) = match (asks_a.get(i), asks_b.get(j)) {
+                (Some(ask_a), Some(ask_b)) if ask_a.price <= ask_b.price => (ask_a, true),
+                (Some(_), Some(ask_b)) => (ask_b, false),
+                (Some(ask_a), None) => (ask_a, true),
+                (None, Some(ask_b)) => (ask_b, false),
+                (None, None) => break,
+            };
+
+            let size = if level.amount < remaining { level.amount } else { remaining };
+            if from_a {
+                amount_a += size;
+                i += 1;
+            } else {
+                amount_b += size;
+                j += 1;
+            }
+            remaining -= size;
+        }
+
+        if remaining > 0 {
+            return Err(ArbitrageError::InsufficientLiquidity);
+        }
+        Ok((amount_a, amount_b))
+    }
+
+    /// Preview the price impact of trading `amount` of `pair` on an exchange without trading.
+    /// `side` is "buy" (walks the asks) or "sell" (walks the bids).
+    /// Returns the average fill price, the worst level touched, and the average's slippage
+    /// from the best level in basis points.
+    pub fn simulate_order_book_impact(env: Env, exchange: String, pair: String, side: String, amount: i128) -> Result<(i128, i128, i128), ArbitrageError> {
+        let book = Self::get_order_book(&env, &exchange, &pair)?;
+        let levels = if side == String::from_str(&env, "buy") {
+            book.asks
+        } else if side == String::from_str(&env, "sell") {
+            book.bids
+        } else {
+            return Err(ArbitrageError::InvalidSide);
+        };
+
+        Self::walk_order_book(&levels, amount).ok_or(ArbitrageError::InsufficientLiquidity)
+    }
+
     /// Scans for arbitrage opportunities across supported assets
     pub fn scan_opportunities(env: Env, assets: Vec<String>, min_profit: i128) -> Result<Vec<ArbitrageOpportunity>, ArbitrageError> {
+        let (opportunities, _failures) = Self::scan_with_diagnostics(env, assets, min_profit)?;
+        Ok(opportunities)
+    }
+
+    /// Scans like `scan_opportunities`, also returning why assets or venues were skipped:
+    /// unsupported assets, failed oracle reads, and registered exchange adapters whose
+    /// order book call failed
+    #[allow(clippy::type_complexity)]
+    pub fn scan_with_diagnostics(env: Env, assets: Vec<String>, min_profit: i128) -> Result<(Vec<ArbitrageOpportunity>, Vec<(String, ArbitrageError)>), ArbitrageError> {
         // Get the Reflector Oracle contract ID from storage or use a default
         let reflector_contract_id = Self::get_reflector_contract_id(&env);
         let reflector_client = ReflectorOracleClient::new(&env, &reflector_contract_id);
         
         let mut opportunities = Vec::new(&env);
+        let mut failures = Vec::new(&env);
+        let near_miss_margin: i128 = env.storage().instance().get(&DataKey::NearMissMargin).unwrap_or(0);
+        let min_liquidity = Self::get_min_liquidity(env.clone());
+        let expiry_time = env.ledger().timestamp() + Self::get_opportunity_ttl(env.clone());
         
         // For each asset, get price data from the oracle
         for asset_code in assets.iter() {
             if !Self::is_asset_supported(env.clone(), asset_code.clone()) {
+                failures.push_back((asset_code.clone(), ArbitrageError::InvalidAsset));
                 continue;
             }
             
             // Get price data from the Reflector Oracle
             let price_data = match reflector_client.try_get_price_data(&asset_code) {
                 Ok(Ok(data)) => data,
-                _ => continue,
+                _ => {
+                    failures.push_back((asset_code.clone(), ArbitrageError::OracleError));
+                    continue;
+                }
             };
             
-            // Simulate checking multiple exchanges
-            // In a real implementation, this would fetch actual order book data
-            let exchanges = vec![&env, String::from_str(&env, "Stellar DEX"), String::from_str(&env, "Soroswap"), String::from_str(&env, "Aqua Network")];
-            
-            // For demonstration, we'll simulate some price differences
-            let mut prices: Map<String, i128> = Map::new(&env);
-            prices.set(String::from_str(&env, "Stellar DEX"), price_data.price);
-            prices.set(String::from_str(&env, "Soroswap"), price_data.price + 100); // Simulate Soroswap having a slightly higher price
-            prices.set(String::from_str(&env, "Aqua Network"), price_data.price - 50); // Simulate Aqua having a slightly lower price
+            let min_confidence = Self::get_asset_min_confidence(env.clone(), asset_code.clone());
             
             // Find arbitrage opportunities by comparing prices across exchanges
-            for i in 0..exchanges.len() {
-                for j in (i + 1)..exchanges.len() {
-                    let exchange_a = exchanges.get(i).unwrap();
-                    let exchange_b = exchanges.get(j).unwrap();
-                    
-                    let price_a = prices.get(exchange_a.clone()).unwrap_or(price_data.price);
-                    let price_b = prices.get(exchange_b.clone()).unwrap_or(price_data.price);
-                    
-                    // Check for arbitrage opportunity (buy low, sell high)
-                    if price_a < price_b {
-                        let profit = price_b - price_a;
-                        if profit >= min_profit {
-                            let opportunity = ArbitrageOpportunity {
-                                asset: asset_code.clone(),
-                                buy_exchange: exchange_a.clone(),
-                                sell_exchange: exchange_b.clone(),
-                                buy_price: price_a,
-                                sell_price: price_b,
-                                available_amount: 1000000, // Simulated amount
-                                estimated_profit: profit,
-                                confidence_score: 95, // Simulated confidence
-                                expiry_time: env.ledger().timestamp() + 30, // Expires in 30 seconds
-                            };
-                            opportunities.push_back(opportunity);
-                        }
-                    } else if price_b < price_a {
-                        let profit = price_a - price_b;
-                        if profit >= min_profit {
-                            let opportunity = ArbitrageOpportunity {
-                                asset: asset_code.clone(),
-                                buy_exchange: exchange_b.clone(),
-                                sell_exchange: exchange_a.clone(),
-                                buy_price: price_b,
-                                sell_price: price_a,
-                                available_amount: 1000000, // Simulated amount
-                                estimated_profit: profit,
-                                confidence_score: 95, // Simulated confidence
-                                expiry_time: env.ledger().timestamp() + 30, // Expires in 30 seconds
-                            };
-                            opportunities.push_back(opportunity);
-                        }
+            for (buy_exchange, sell_exchange, buy_price, sell_price) in Self::venue_pairs(&env, &asset_code, price_data.price, LegOrder::BuyFirst, &mut failures).iter() {
+                let profit = sell_price - buy_price;
+                if profit < min_profit {
+                    // Publish near-misses so operators can see what a lower threshold would admit
+                    if near_miss_margin > 0 && profit + near_miss_margin >= min_profit {
+                        NearMiss {
+                            asset: asset_code.clone(),
+                            buy_exchange,
+                            sell_exchange,
+                            profit,
+                            min_profit,
+                        }.publish(&env);
+                    }
+                    continue;
+                }
+                
+                // Venues without an adapter have no book to check; a failing adapter drops the pair
+                let buy_book = match Self::get_order_book(&env, &buy_exchange, &asset_code) {
+                    Ok(book) => Some(book),
+                    Err(ArbitrageError::UnknownExchange) => None,
+                    Err(error) => {
+                        failures.push_back((asset_code.clone(), error));
+                        continue;
                     }
+                };
+                
+                if min_liquidity > 0 && Self::depth_at_price(&buy_book, buy_price, true) < min_liquidity {
+                    // Too thin to fill at the quoted price
+                    continue;
                 }
+                
+                let available_amount = 1000000; // Simulated amount
+                let (estimated_slippage_bps, confidence_score) = Self::order_book_impact(&buy_book, available_amount, true);
+                if confidence_score < min_confidence {
+                    // Not confident enough for a high-risk asset
+                    continue;
+                }
+                
+                let opportunity = ArbitrageOpportunity {
+                    asset: asset_code.clone(),
+                    buy_exchange,
+                    sell_exchange,
+                    buy_price,
+                    sell_price,
+                    available_amount,
+                    estimated_profit: profit,
+                    confidence_score,
+                    estimated_slippage_bps,
+                    expiry_time,
+                };
+                opportunities.push_back(opportunity);
             }
         }
         
+        Ok((Self::sort_opportunities(&env, opportunities), failures))
+    }
+    
+    /// Finds sell-first opportunities for the assets held in `inventory`, keyed by asset code.
+    /// Each pair is oriented to sell on the dearer venue and buy back on the cheaper one, and
+    /// is sized by the inventory on hand. The sell venue's bids face the same liquidity and
+    /// confidence floors the buy-first scan applies to the buy venue's asks. Unsupported assets
+    /// and failed oracle reads are skipped.
+    pub fn scan_sell_first_opportunities(env: Env, inventory: Map<String, i128>, min_profit: i128) -> Result<Vec<SellFirstOpportunity>, ArbitrageError> {
+        let reflector_client = ReflectorOracleClient::new(&env, &Self::get_reflector_contract_id(&env));
+        let min_liquidity = Self::get_min_liquidity(env.clone());
+        let expiry_time = env.ledger().timestamp() + Self::get_opportunity_ttl(env.clone());
+        let mut opportunities = Vec::new(&env);
+
+        for (asset_code, inventory_amount) in inventory.iter() {
+            if inventory_amount <= 0 || !Self::is_asset_supported(env.clone(), asset_code.clone()) {
+                continue;
+            }
+
+            let price_data = match reflector_client.try_get_price_data(&asset_code) {
+                Ok(Ok(data)) => data,
+                _ => continue,
+            };
+
+            let min_confidence = Self::get_asset_min_confidence(env.clone(), asset_code.clone());
+
+            for (sell_exchange, buy_exchange, sell_price, buy_price) in Self::venue_pairs(&env, &asset_code, price_data.price, LegOrder::SellFirst, &mut Vec::new(&env)).iter() {
+                let profit = sell_price - buy_price;
+                if profit < min_profit {
+                    continue;
+                }
+
+                // Venues without an adapter have no book to check; a failing adapter drops the pair
+                let sell_book = match Self::get_order_book(&env, &sell_exchange, &asset_code) {
+                    Ok(book) => Some(book),
+                    Err(ArbitrageError::UnknownExchange) => None,
+                    Err(_) => continue,
+                };
+
+                if min_liquidity > 0 && Self::depth_at_price(&sell_book, sell_price, false) < min_liquidity {
+                    // Too thin to sell into at the quoted price
+                    continue;
+                }
+
+                let (_, confidence_score) = Self::order_book_impact(&sell_book, inventory_amount, false);
+                if confidence_score < min_confidence {
+                    // Not confident enough for a high-risk asset
+                    continue;
+                }
+
+                opportunities.push_back(SellFirstOpportunity {
+                    asset: asset_code.clone(),
+                    sell_exchange,
+                    buy_exchange,
+                    sell_price,
+                    buy_price,
+                    inventory_amount,
+                    estimated_profit: profit,
+                    expiry_time,
+                });
+            }
+        }
+
         Ok(opportunities)
     }
     
+    /// Scans like `scan_opportunities` but groups the results by asset code
+    pub fn scan_opportunities_map(env: Env, assets: Vec<String>, min_profit: i128) -> Result<Map<String, Vec<ArbitrageOpportunity>>, ArbitrageError> {
+        let opportunities = Self::scan_opportunities(env.clone(), assets, min_profit)?;
+
+        let mut by_asset: Map<String, Vec<ArbitrageOpportunity>> = Map::new(&env);
+        for opportunity in opportunities.iter() {
+            let mut asset_opportunities = by_asset.get(opportunity.asset.clone()).unwrap_or(Vec::new(&env));
+            let asset = opportunity.asset.clone();
+            asset_opportunities.push_back(opportunity);
+            by_asset.set(asset, asset_opportunities);
+        }
+
+        Ok(by_asset)
+    }
+    
+    /// Re-check an opportunity against a fresh oracle price just before executing it. Valid while
+    /// it has not expired, still clears the spread filters, and its spread has not shrunk more
+    /// than the revalidation tolerance below `estimated_profit`.
+    pub fn is_opportunity_valid(env: Env, opportunity: ArbitrageOpportunity, oracle_address: Address) -> bool {
+        if env.ledger().timestamp() >= opportunity.expiry_time {
+            return false;
+        }
+
+        let price_data = match ReflectorOracleClient::new(&env, &oracle_address).try_get_price_data(&opportunity.asset) {
+            Ok(Ok(data)) => data,
+            _ => return false,
+        };
+
+        let prices = Self::venue_prices(&env, &opportunity.asset, price_data.price, &mut Vec::new(&env));
+        let (buy_price, sell_price) = match (prices.get(opportunity.buy_exchange), prices.get(opportunity.sell_exchange)) {
+            (Some(buy_price), Some(sell_price)) => (buy_price, sell_price),
+            _ => return false,
+        };
+
+        if !Self::clears_spread_filters(&env, buy_price, sell_price) {
+            return false;
+        }
+
+        let min_profit = opportunity.estimated_profit * (10000 - REVALIDATION_TOLERANCE_BPS) / 10000;
+        sell_price - buy_price >= min_profit
+    }
+
+    /// Minimum sell price that recovers the buy cost plus fees, rounded up
+    pub fn break_even_price(buy_price: i128, amount: i128, total_fee_bps: i128) -> i128 {
+        if amount <= 0 {
+            return buy_price;
+        }
+
+        let total_cost = buy_price * amount * (10000 + total_fee_bps);
+        let divisor = amount * 10000;
+        (total_cost + divisor - 1) / divisor
+    }
+    
+    /// Smallest amount whose profit, after `fee_bps` and the expected `slippage_bps` (such as an
+    /// opportunity's `estimated_slippage_bps`) on the buy cost, exceeds `gas_cost`, the network
+    /// cost of executing both legs in the units of price × amount. Returns 0 when the spread
+    /// doesn't cover the fee and slippage at any size.
+    pub fn min_profitable_size(buy_price: i128, sell_price: i128, fee_bps: i128, slippage_bps: i128, gas_cost: i128) -> i128 {
+        // Profit per unit, scaled by 10000 to keep the fee and slippage exact
+        let scaled_margin = sell_price * 10000 - buy_price * (10000 + fee_bps + slippage_bps);
+        if buy_price <= 0 || scaled_margin <= 0 {
+            return 0;
+        }
+
+        gas_cost.max(0) * 10000 / scaled_margin + 1
+    }
+    
+    /// Implied A->C rate from an A->B and a B->C rate, all with 7 decimals.
+    /// Rounds down, so a triangular route is never credited with a better rate than it has.
+    pub fn implied_rate(price_ab: i128, price_bc: i128) -> i128 {
+        if price_ab <= 0 || price_bc <= 0 {
+            return 0;
+        }
+        price_ab * price_bc / RATE_SCALE
+    }
+    
     /// Validates if an asset is supported by the system
     pub fn is_asset_supported(env: Env, asset_code: String) -> bool {
         let supported_assets = Self::get_supported_assets(env);
@@ -183,6 +616,208 @@ impl ArbitrageDetector {
         false
     }
     
+    /// Order opportunities by asset, then buy exchange, then sell exchange, so output
+    /// does not depend on the order assets and venues were scanned in
+    fn sort_opportunities(env: &Env, opportunities: Vec<ArbitrageOpportunity>) -> Vec<ArbitrageOpportunity> {
+        let mut sorted: Vec<ArbitrageOpportunity> = Vec::new(env);
+        for opportunity in opportunities.iter() {
+            let key = (&opportunity.asset, &opportunity.buy_exchange, &opportunity.sell_exchange);
+            let mut position = sorted.len();
+            for (i, existing) in sorted.iter().enumerate() {
+                if key < (&existing.asset, &existing.buy_exchange, &existing.sell_exchange) {
+                    position = i as u32;
+                    break;
+                }
+            }
+            sorted.insert(position, opportunity);
+        }
+        sorted
+    }
+
+    /// Pairs of scanned venues whose quotes differ by enough to clear the fee break-even and
+    /// minimum spread. Each is (first venue, second venue, first price, second price) in the
+    /// order the legs run: the cheaper venue comes first when buying first, the dearer one
+    /// when selling first. Adapter failures met while pricing venues are appended to `failures`.
+    fn venue_pairs(env: &Env, asset_code: &String, oracle_price: i128, leg_order: LegOrder, failures: &mut Vec<(String, ArbitrageError)>) -> Vec<(String, String, i128, i128)> {
+        let prices = Self::venue_prices(env, asset_code, oracle_price, failures);
+        let exchanges = Self::get_scan_exchanges(env.clone());
+        let mut pairs = Vec::new(env);
+
+        for i in 0..exchanges.len() {
+            for j in (i + 1)..exchanges.len() {
+                let exchange_a = exchanges.get(i).unwrap();
+                let exchange_b = exchanges.get(j).unwrap();
+
+                // A venue without a quote can't be priced, so it takes part in no pair
+                let (Some(price_a), Some(price_b)) = (prices.get(exchange_a.clone()), prices.get(exchange_b.clone())) else {
+                    continue;
+                };
+
+                // Orient the pair so we buy on the cheaper exchange and sell on the dearer one
+                let (buy_exchange, sell_exchange, buy_price, sell_price) = if price_a < price_b {
+                    (exchange_a, exchange_b, price_a, price_b)
+                } else if price_b < price_a {
+                    (exchange_b, exchange_a, price_b, price_a)
+                } else {
+                    continue;
+                };
+
+                if !Self::clears_spread_filters(env, buy_price, sell_price) {
+                    continue;
+                }
+
+                pairs.push_back(match leg_order {
+                    LegOrder::BuyFirst => (buy_exchange, sell_exchange, buy_price, sell_price),
+                    LegOrder::SellFirst => (sell_exchange, buy_exchange, sell_price, buy_price),
+                });
+            }
+        }
+        pairs
+    }
+
+    /// Per-venue prices for an asset across the scanned venues. Venues with a registered adapter
+    /// are quoted from its book; built-in venues without one get a simulated quote around the
+    /// oracle price. A venue whose adapter fails or quotes nothing is left out, and the failure
+    /// appended to `failures`.
+    fn venue_prices(env: &Env, asset_code: &String, oracle_price: i128, failures: &mut Vec<(String, ArbitrageError)>) -> Map<String, i128> {
+        let mut prices: Map<String, i128> = Map::new(env);
+        for exchange in Self::get_scan_exchanges(env.clone()).iter() {
+            let price = match Self::get_order_book(env, &exchange, asset_code) {
+                Ok(book) => Self::book_quote(&book),
+                Err(ArbitrageError::UnknownExchange) => Self::simulated_quote(env, &exchange, oracle_price),
+                Err(error) => {
+                    failures.push_back((asset_code.clone(), error));
+                    None
+                }
+            };
+            if let Some(price) = price {
+                prices.set(exchange, price);
+            }
+        }
+        prices
+    }
+
+    /// Simulated quote for the built-in venues, which have no adapter to ask; None for any other venue
+    fn simulated_quote(env: &Env, exchange: &String, oracle_price: i128) -> Option<i128> {
+        if *exchange == String::from_str(env, "Stellar DEX") {
+            Some(oracle_price)
+        } else if *exchange == String::from_str(env, "Soroswap") {
+            Some(oracle_price + 100) // Simulate Soroswap having a slightly higher price
+        } else if *exchange == String::from_str(env, "Aqua Network") {
+            Some(oracle_price - 50) // Simulate Aqua having a slightly lower price
+        } else {
+            None
+        }
+    }
+
+    /// A book's mid price, or the price of its only quoted side; None for an empty book
+    fn book_quote(book: &OrderBookData) -> Option<i128> {
+        match (book.bids.first(), book.asks.first()) {
+            (Some(bid), Some(ask)) => Some((bid.price + ask.price) / 2),
+            (Some(bid), None) => Some(bid.price),
+            (None, Some(ask)) => Some(ask.price),
+            (None, None) => None,
+        }
+    }
+
+    /// Check a buy/sell price pair against the fee break-even and the minimum spread
+    fn clears_spread_filters(env: &Env, buy_price: i128, sell_price: i128) -> bool {
+        if buy_price <= 0 || sell_price < Self::break_even_price(buy_price, 1000000, TOTAL_FEE_BPS) {
+            return false;
+        }
+
+        let spread_bps = (sell_price - buy_price) * 10000 / buy_price;
+        spread_bps >= Self::get_min_spread_bps(env.clone())
+    }
+
+    /// Slippage of buying (walking the asks) or selling (walking the bids) `amount` against a
+    /// venue's book, as `simulate_order_book_impact` reports it. Returns (slippage bps,
+    /// confidence): venues without a book report no slippage and keep the base confidence;
+    /// books too thin to fill the amount report 100% and score 0.
+    fn order_book_impact(book: &Option<OrderBookData>, amount: i128, is_buy: bool) -> (i128, i128) {
+        let book = match book {
+            Some(book) => book,
+            None => return (0, BASE_CONFIDENCE),
+        };
+
+        let levels = if is_buy { &book.asks } else { &book.bids };
+        match Self::walk_order_book(levels, amount) {
+            Some((_, _, slippage_bps)) => (slippage_bps, (BASE_CONFIDENCE - slippage_bps / SLIPPAGE_BPS_PER_CONFIDENCE_POINT).max(0)),
+            None => (10000, 0),
+        }
+    }
+
+    /// Volume a book offers at `price` or better: asks at or below it when buying, bids at or
+    /// above it when selling; 0 without a book
+    fn depth_at_price(book: &Option<OrderBookData>, price: i128, is_buy: bool) -> i128 {
+        let book = match book {
+            Some(book) => book,
+            None => return 0,
+        };
+
+        let levels = if is_buy { &book.asks } else { &book.bids };
+        let mut depth = 0;
+        for level in levels.iter() {
+            if (is_buy && level.price <= price) || (!is_buy && level.price >= price) {
+                depth += level.amount;
+            }
+        }
+        depth
+    }
+
+    /// Walk book levels, best first, until `amount` is filled.
+    /// Returns (average price, worst price, slippage bps), or None if the book is too thin.
+    fn walk_order_book(levels: &Vec<OrderBookEntry>, amount: i128) -> Option<(i128, i128, i128)> {
+        let best_price = levels.first()?.price;
+        if amount <= 0 || best_price <= 0 {
+            return None;
+        }
+
+        let mut remaining = amount;
+        let mut total_cost = 0;
+        let mut worst_price = best_price;
+        for level in levels.iter() {
+            if remaining == 0 {
+                break;
+            }
+            let size = if level.amount < remaining { level.amount } else { remaining };
+            total_cost += size * level.price;
+            worst_price = level.price;
+            remaining -= size;
+        }
+
+        if remaining > 0 {
+            return None;
+        }
+
+        let average_price = total_cost / amount;
+        let slippage_bps = (average_price - best_price).abs() * 10000 / best_price;
+        Some((average_price, worst_price, slippage_bps))
+    }
+
+    /// Fetch an asset's order book from the adapter registered for an exchange
+    fn get_order_book(env: &Env, exchange: &String, asset_code: &String) -> Result<OrderBookData, ArbitrageError> {
+        let adapter: Address = env.storage()
+            .instance()
+            .get(&DataKey::ExchangeAdapter(exchange.clone()))
+            .ok_or(ArbitrageError::UnknownExchange)?;
+
+        match ExchangeClient::new(env, &adapter).try_get_order_book(asset_code) {
+            Ok(Ok(book)) => Ok(book),
+            _ => Err(ArbitrageError::ExchangeCallFailed),
+        }
+    }
+
+    /// Require authorization from the stored admin
+    fn require_admin(env: &Env) -> Result<(), ArbitrageError> {
+        let admin: Address = env.storage()
+            .instance()
+            .get(&DataKey::Admin)
+            .ok_or(ArbitrageError::NotInitialized)?;
+        admin.require_auth();
+        Ok(())
+    }
+
     /// Get the Reflector Oracle contract ID
     fn get_reflector_contract_id(env: &Env) -> Address {
         // In a real implementation, this would be stored in contract storage
@@ -196,4 +831,532 @@ impl ArbitrageDetector {
         // This is a placeholder for the actual implementation
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test_arbitrage_detector {
+    use super::*;
+    use soroban_sdk::{Env, testutils::{Address as _, Events as _, Ledger as _}};
+    use reflector_oracle_client::{ReflectorPriceData, testutils::{register_mock_reflector, MockReflectorClient}};
+
+    // Stand-in for an exchange adapter serving a fixed order book
+    #[contract]
+    pub struct MockExchange;
+
+    #[contractimpl]
+    impl MockExchange {
+        pub fn set_order_book(env: Env, book: OrderBookData) {
+            env.storage().instance().set(&String::from_str(&env, "book"), &book);
+        }
+
+        pub fn get_order_book(env: Env, _asset_code: String) -> OrderBookData {
+            env.storage().instance().get(&String::from_str(&env, "book")).unwrap()
+        }
+    }
+
+    fn register_mock_exchange(env: &Env, client: &ArbitrageDetectorClient, name: &str, asks: &[(i128, i128)]) {
+        register_mock_exchange_with_bids(env, client, name, &[], asks);
+    }
+
+    fn register_mock_exchange_with_bids(env: &Env, client: &ArbitrageDetectorClient, name: &str, bids: &[(i128, i128)], asks: &[(i128, i128)]) {
+        let mut book = OrderBookData {
+            bids: Vec::new(env),
+            asks: Vec::new(env),
+            timestamp: env.ledger().timestamp(),
+        };
+        for (price, amount) in bids.iter() {
+            book.bids.push_back(OrderBookEntry { price: *price, amount: *amount });
+        }
+        for (price, amount) in asks.iter() {
+            book.asks.push_back(OrderBookEntry { price: *price, amount: *amount });
+        }
+
+        let exchange_id = env.register(MockExchange, ());
+        MockExchangeClient::new(env, &exchange_id).set_order_book(&book);
+        client.register_exchange(&String::from_str(env, name), &exchange_id);
+    }
+
+    fn setup_test<'a>() -> (Env, ArbitrageDetectorClient<'a>) {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        register_mock_reflector(&env);
+        set_oracle_price(&env, 10000);
+
+        let contract_id = env.register(ArbitrageDetector, ());
+        let client = ArbitrageDetectorClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+        (env, client)
+    }
+
+    // Quote every asset at `price` through the shared Reflector mock
+    fn set_oracle_price(env: &Env, price: i128) {
+        let reflector_id = ArbitrageDetector::get_reflector_contract_id(env);
+        MockReflectorClient::new(env, &reflector_id).set_price(&ReflectorPriceData {
+            price,
+            timestamp: env.ledger().timestamp(),
+            confidence: 95,
+            volume_24h: 1000000_0000000,
+        });
+    }
+
+    fn assets(env: &Env) -> Vec<String> {
+        vec![env, String::from_str(env, "AQUA")]
+    }
+
+    #[test]
+    fn test_break_even_price() {
+        let (_env, client) = setup_test();
+
+        // No fees: break-even is the buy price itself
+        assert_eq!(client.break_even_price(&10000, &1000, &0), 10000);
+
+        // 0.3% combined fees
+        assert_eq!(client.break_even_price(&10000, &1000, &30), 10030);
+
+        // 0.6% combined fees
+        assert_eq!(client.break_even_price(&10000, &1000, &60), 10060);
+
+        // Fractional break-even rounds up so the sell price always clears it
+        assert_eq!(client.break_even_price(&10001, &1, &25), 10027);
+    }
+
+    #[test]
+    fn test_min_spread_bps_filters_small_spreads() {
+        let (env, client) = setup_test();
+
+        // At a 10000 oracle price the simulated venues give a 100 bps
+        // Stellar DEX -> Soroswap spread and a ~150 bps Aqua -> Soroswap spread
+        let opportunities = client.scan_opportunities(&assets(&env), &100);
+        assert_eq!(opportunities.len(), 2);
+
+        // Both clear min_profit, but only the wider spread clears 120 bps
+        client.set_min_spread_bps(&120);
+        let opportunities = client.scan_opportunities(&assets(&env), &100);
+        assert_eq!(opportunities.len(), 1);
+
+        let opportunity = opportunities.get(0).unwrap();
+        assert_eq!(opportunity.buy_exchange, String::from_str(&env, "Aqua Network"));
+        assert_eq!(opportunity.sell_exchange, String::from_str(&env, "Soroswap"));
+    }
+
+    #[test]
+    fn test_compute_optimal_split_routes_overflow() {
+        let (env, client) = setup_test();
+        register_mock_exchange(&env, &client, "Stellar DEX", &[(10000, 300), (10200, 500)]);
+        register_mock_exchange(&env, &client, "Soroswap", &[(10100, 500)]);
+
+        // More than either book holds: 300 at Stellar DEX's best price, all 500 of Soroswap's
+        // cheaper level, then the last 200 from Stellar DEX's second level
+        let split = client.compute_optimal_split(
+            &String::from_str(&env, "AQUA"),
+            &String::from_str(&env, "Stellar DEX"),
+            &String::from_str(&env, "Soroswap"),
+            &1000,
+        );
+        assert_eq!(split, (500, 500));
+
+        // Beyond the 1300 both books hold together
+        let split = client.try_compute_optimal_split(
+            &String::from_str(&env, "AQUA"),
+            &String::from_str(&env, "Stellar DEX"),
+            &String::from_str(&env, "Soroswap"),
+            &1301,
+        );
+        assert_eq!(split, Err(Ok(ArbitrageError::InsufficientLiquidity)));
+
+        // Size within the best level stays on one venue
+        let split = client.compute_optimal_split(
+            &String::from_str(&env, "AQUA"),
+            &String::from_str(&env, "Stellar DEX"),
+            &String::from_str(&env, "Soroswap"),
+            &100,
+        );
+        assert_eq!(split, (100, 0));
+    }
+
+    #[test]
+    fn test_near_miss_emits_event() {
+        let (env, client) = setup_test();
+        client.set_near_miss_margin(&30);
+
+        // At min_profit 120, Stellar DEX -> Soroswap (100) is a near-miss,
+        // Aqua -> Stellar DEX (50) is a far miss and Aqua -> Soroswap (150) passes
+        let opportunities = client.scan_opportunities(&assets(&env), &120);
+        assert_eq!(opportunities.len(), 1);
+        assert_eq!(env.events().all().len(), 1);
+    }
+
+    #[test]
+    fn test_opportunity_ttl() {
+        let (env, client) = setup_test();
+        assert_eq!(client.get_opportunity_ttl(), 30);
+
+        client.set_opportunity_ttl(&10);
+        let opportunities = client.scan_opportunities(&assets(&env), &100);
+        assert!(!opportunities.is_empty());
+        for opportunity in opportunities.iter() {
+            assert_eq!(opportunity.expiry_time, env.ledger().timestamp() + 10);
+        }
+    }
+
+    #[test]
+    fn test_get_aggregate_liquidity() {
+        let (env, client) = setup_test();
+        register_mock_exchange_with_bids(&env, &client, "Stellar DEX", &[(9900, 400)], &[(10000, 300), (10200, 500)]);
+        register_mock_exchange_with_bids(&env, &client, "Soroswap", &[(9950, 250)], &[(10100, 500)]);
+
+        // 400 + 300 + 500 on Stellar DEX, 250 + 500 on Soroswap
+        assert_eq!(client.get_aggregate_liquidity(&String::from_str(&env, "AQUA")), 1950);
+    }
+
+    #[test]
+    fn test_simulate_order_book_impact() {
+        let (env, client) = setup_test();
+        register_mock_exchange_with_bids(
+            &env,
+            &client,
+            "Stellar DEX",
+            &[(9900, 10), (9800, 10)],
+            &[(10000, 10), (10100, 10), (10300, 10)],
+        );
+        let exchange = String::from_str(&env, "Stellar DEX");
+        let pair = String::from_str(&env, "AQUA");
+
+        // Buying 25 takes 10 @ 10000, 10 @ 10100 and 5 @ 10300
+        let impact = client.simulate_order_book_impact(&exchange, &pair, &String::from_str(&env, "buy"), &25);
+        assert_eq!(impact, (10100, 10300, 100));
+
+        // Selling 15 takes 10 @ 9900 and 5 @ 9800
+        let impact = client.simulate_order_book_impact(&exchange, &pair, &String::from_str(&env, "sell"), &15);
+        assert_eq!(impact, (9866, 9800, 34));
+
+        // More than the book holds
+        let result = client.try_simulate_order_book_impact(&exchange, &pair, &String::from_str(&env, "buy"), &31);
+        assert_eq!(result, Err(Ok(ArbitrageError::InsufficientLiquidity)));
+    }
+
+    #[test]
+    fn test_scan_opportunities_map_groups_by_asset() {
+        let (env, client) = setup_test();
+        let aqua = String::from_str(&env, "AQUA");
+        let eurc = String::from_str(&env, "EURC");
+        let unsupported = String::from_str(&env, "DOGE");
+
+        let by_asset = client.scan_opportunities_map(&vec![&env, aqua.clone(), eurc.clone(), unsupported.clone()], &100);
+        assert_eq!(by_asset.len(), 2);
+        assert!(!by_asset.contains_key(unsupported));
+
+        for asset in [aqua, eurc] {
+            let opportunities = by_asset.get(asset.clone()).unwrap();
+            assert_eq!(opportunities.len(), 2);
+            for opportunity in opportunities.iter() {
+                assert_eq!(opportunity.asset, asset);
+            }
+        }
+    }
+
+    #[test]
+    fn test_opportunities_span_distinct_venues() {
+        let (env, client) = setup_test();
+
+        let opportunities = client.scan_opportunities(&assets(&env), &0);
+        assert_eq!(opportunities.len(), 2);
+        for opportunity in opportunities.iter() {
+            assert_ne!(opportunity.buy_exchange, opportunity.sell_exchange);
+        }
+    }
+
+    #[test]
+    fn test_min_liquidity_filters_thin_books() {
+        let (env, client) = setup_test();
+        // Deep asks at Stellar DEX's 10000 quote, a thin level at Aqua's 9950 and more behind it
+        register_mock_exchange(&env, &client, "Stellar DEX", &[(10000, 5000)]);
+        register_mock_exchange(&env, &client, "Aqua Network", &[(9950, 10), (10050, 5000)]);
+
+        assert_eq!(client.scan_opportunities(&assets(&env), &100).len(), 2);
+
+        client.set_min_liquidity(&1000);
+        let opportunities = client.scan_opportunities(&assets(&env), &100);
+        assert_eq!(opportunities.len(), 1);
+        assert_eq!(opportunities.get(0).unwrap().buy_exchange, String::from_str(&env, "Stellar DEX"));
+    }
+
+    #[test]
+    fn test_confidence_penalizes_slippage() {
+        let confidence_buying_on_stellar_dex = |asks: &[(i128, i128)]| {
+            let (env, client) = setup_test();
+            register_mock_exchange(&env, &client, "Stellar DEX", asks);
+
+            let stellar_dex = String::from_str(&env, "Stellar DEX");
+            let opportunities = client.scan_opportunities(&assets(&env), &100);
+            opportunities.iter().find(|o| o.buy_exchange == stellar_dex).unwrap().confidence_score
+        };
+
+        // Same 100 bps spread either way; the second book averages 10500 for the simulated size
+        let deep = confidence_buying_on_stellar_dex(&[(10000, 1000000)]);
+        let steep = confidence_buying_on_stellar_dex(&[(10000, 500000), (11000, 500000)]);
+        assert_eq!(deep, 95);
+        assert_eq!(steep, 45);
+    }
+
+    #[test]
+    fn test_is_opportunity_valid_rechecks_price_and_expiry() {
+        let (env, client) = setup_test();
+        let reflector_id = ArbitrageDetector::get_reflector_contract_id(&env);
+
+        let stellar_dex = String::from_str(&env, "Stellar DEX");
+        let opportunities = client.scan_opportunities(&assets(&env), &100);
+        let opportunity = || opportunities.iter().find(|o| o.buy_exchange == stellar_dex).unwrap();
+        assert!(client.is_opportunity_valid(&opportunity(), &reflector_id));
+
+        // At 20000 the same 100 spread is 50 bps, below the 60 bps fee break-even
+        set_oracle_price(&env, 20000);
+        assert!(!client.is_opportunity_valid(&opportunity(), &reflector_id));
+
+        // Back at the original price, but past the opportunity's expiry
+        set_oracle_price(&env, 10000);
+        assert!(client.is_opportunity_valid(&opportunity(), &reflector_id));
+        let ttl = client.get_opportunity_ttl();
+        env.ledger().with_mut(|li| {
+            li.timestamp += ttl;
+        });
+        assert!(!client.is_opportunity_valid(&opportunity(), &reflector_id));
+    }
+
+    #[test]
+    fn test_opportunity_reports_estimated_slippage() {
+        let (env, client) = setup_test();
+        register_mock_exchange(&env, &client, "Stellar DEX", &[(10000, 500000), (11000, 500000)]);
+        let stellar_dex = String::from_str(&env, "Stellar DEX");
+        let aqua = String::from_str(&env, "AQUA");
+
+        let opportunities = client.scan_opportunities(&assets(&env), &100);
+        let opportunity = opportunities.iter().find(|o| o.buy_exchange == stellar_dex).unwrap();
+
+        let (_, _, slippage_bps) = client.simulate_order_book_impact(&stellar_dex, &aqua, &String::from_str(&env, "buy"), &opportunity.available_amount);
+        assert_eq!(opportunity.estimated_slippage_bps, slippage_bps);
+        assert_eq!(opportunity.estimated_slippage_bps, 500);
+    }
+
+    #[test]
+    fn test_scan_with_diagnostics_reports_failures() {
+        let (env, client) = setup_test();
+        // An adapter with no book stored fails every get_order_book call
+        let broken_adapter = env.register(MockExchange, ());
+        client.register_exchange(&String::from_str(&env, "Stellar DEX"), &broken_adapter);
+
+        let aqua = String::from_str(&env, "AQUA");
+        let unsupported = String::from_str(&env, "DOGE");
+        let (opportunities, failures) = client.scan_with_diagnostics(&vec![&env, aqua.clone(), unsupported.clone()], &100);
+
+        // Aqua -> Soroswap survives; Stellar DEX -> Soroswap is dropped and reported
+        assert_eq!(opportunities.len(), 1);
+        assert_eq!(opportunities.get(0).unwrap().buy_exchange, String::from_str(&env, "Aqua Network"));
+        assert_eq!(
+            failures,
+            vec![&env, (aqua, ArbitrageError::ExchangeCallFailed), (unsupported, ArbitrageError::InvalidAsset)]
+        );
+    }
+
+    #[test]
+    fn test_opportunities_sorted_deterministically() {
+        let (env, client) = setup_test();
+        let aqua = String::from_str(&env, "AQUA");
+        let eurc = String::from_str(&env, "EURC");
+
+        let forward = client.scan_opportunities(&vec![&env, aqua.clone(), eurc.clone()], &100);
+        let reversed = client.scan_opportunities(&vec![&env, eurc.clone(), aqua.clone()], &100);
+
+        let expected = [
+            (aqua.clone(), "Aqua Network", "Soroswap"),
+            (aqua, "Stellar DEX", "Soroswap"),
+            (eurc.clone(), "Aqua Network", "Soroswap"),
+            (eurc, "Stellar DEX", "Soroswap"),
+        ];
+        for opportunities in [forward, reversed] {
+            assert_eq!(opportunities.len(), expected.len() as u32);
+            for (opportunity, (asset, buy_exchange, sell_exchange)) in opportunities.iter().zip(expected.iter()) {
+                assert_eq!(&opportunity.asset, asset);
+                assert_eq!(opportunity.buy_exchange, String::from_str(&env, buy_exchange));
+                assert_eq!(opportunity.sell_exchange, String::from_str(&env, sell_exchange));
+            }
+        }
+    }
+
+    #[test]
+    fn test_implied_rate() {
+        let (_env, client) = setup_test();
+
+        // 2.0 A/B and 0.5 B/C give 1.0 A/C
+        assert_eq!(client.implied_rate(&2_0000000, &5000000), 1_0000000);
+        assert_eq!(client.implied_rate(&1_5000000, &1_5000000), 2_2500000);
+
+        // Results below the last decimal round down
+        assert_eq!(client.implied_rate(&3_0000000, &3333333), 9999999);
+        assert_eq!(client.implied_rate(&1, &5000000), 0);
+
+        // Missing legs imply no rate
+        assert_eq!(client.implied_rate(&0, &1_0000000), 0);
+        assert_eq!(client.implied_rate(&1_0000000, &-1), 0);
+    }
+
+    #[test]
+    fn test_list_exchanges_and_assets() {
+        let (env, client) = setup_test();
+        assert_eq!(client.list_exchanges().len(), 0);
+
+        register_mock_exchange(&env, &client, "Stellar DEX", &[(10000, 100)]);
+        register_mock_exchange(&env, &client, "Soroswap", &[(10100, 100)]);
+        register_mock_exchange(&env, &client, "Aqua Network", &[(9950, 100)]);
+        // Re-registering replaces the adapter without listing the exchange twice
+        register_mock_exchange(&env, &client, "Soroswap", &[(10050, 100)]);
+
+        assert_eq!(
+            client.list_exchanges(),
+            vec![&env, String::from_str(&env, "Stellar DEX"), String::from_str(&env, "Soroswap"), String::from_str(&env, "Aqua Network")]
+        );
+        assert_eq!(
+            client.list_assets(),
+            vec![
+                &env,
+                String::from_str(&env, "AQUA"),
+                String::from_str(&env, "yUSDC"),
+                String::from_str(&env, "EURC"),
+                String::from_str(&env, "BTCLN"),
+                String::from_str(&env, "KALE"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_high_risk_asset_requires_higher_confidence() {
+        let (env, client) = setup_test();
+        // Averages 10100 for the simulated size: 100 bps of slippage, scoring 85
+        register_mock_exchange(&env, &client, "Stellar DEX", &[(10000, 800000), (10500, 200000)]);
+        let stellar_dex = String::from_str(&env, "Stellar DEX");
+        let aqua = String::from_str(&env, "AQUA");
+        let kale = String::from_str(&env, "KALE");
+
+        client.set_high_risk_asset(&kale, &90);
+        assert_eq!(client.get_asset_min_confidence(&kale), 90);
+        assert_eq!(client.get_asset_min_confidence(&aqua), 0);
+
+        let opportunities = client.scan_opportunities(&vec![&env, aqua.clone(), kale.clone()], &100);
+        let buying_on_stellar_dex = |asset: &String| {
+            opportunities.iter().find(|o| o.asset == *asset && o.buy_exchange == stellar_dex)
+        };
+        assert_eq!(buying_on_stellar_dex(&aqua).unwrap().confidence_score, 85);
+        assert!(buying_on_stellar_dex(&kale).is_none());
+    }
+
+    #[test]
+    fn test_min_profitable_size() {
+        let (_env, client) = setup_test();
+
+        // A 70 bps spread nets 10 per unit after the 60 bps fee, so 100000 units only match a
+        // 1000000 gas cost
+        assert_eq!(client.min_profitable_size(&10000, &10070, &60, &0, &1_000_000), 100001);
+
+        // Halving the gas cost halves the size
+        assert_eq!(client.min_profitable_size(&10000, &10070, &60, &0, &500_000), 50001);
+
+        // A wider spread needs less size, and 50 bps of slippage eats half of what it nets
+        assert_eq!(client.min_profitable_size(&10000, &10160, &60, &0, &1_000_000), 10001);
+        assert_eq!(client.min_profitable_size(&10000, &10160, &60, &50, &1_000_000), 20001);
+
+        // Spreads at or inside the fee and slippage never pay
+        assert_eq!(client.min_profitable_size(&10000, &10060, &60, &0, &1_000_000), 0);
+        assert_eq!(client.min_profitable_size(&10000, &10110, &60, &50, &1_000_000), 0);
+        assert_eq!(client.min_profitable_size(&10000, &9990, &0, &0, &1_000_000), 0);
+    }
+
+    #[test]
+    fn test_sell_first_opportunities_use_inventory() {
+        let (env, client) = setup_test();
+        let soroswap = String::from_str(&env, "Soroswap");
+
+        let mut inventory = Map::new(&env);
+        inventory.set(String::from_str(&env, "AQUA"), 500_0000000);
+        inventory.set(String::from_str(&env, "KALE"), 0);
+
+        // Soroswap quotes highest, so held AQUA is sold there and bought back on either cheaper venue
+        let opportunities = client.scan_sell_first_opportunities(&inventory, &100);
+        assert_eq!(opportunities.len(), 2);
+
+        let via_aqua = opportunities.get(1).unwrap();
+        assert_eq!(via_aqua.sell_exchange, soroswap);
+        assert_eq!(via_aqua.buy_exchange, String::from_str(&env, "Aqua Network"));
+        assert_eq!(via_aqua.estimated_profit, 150);
+        assert_eq!(via_aqua.inventory_amount, 500_0000000);
+
+        let via_stellar_dex = opportunities.get(0).unwrap();
+        assert_eq!(via_stellar_dex.sell_exchange, soroswap);
+        assert_eq!(via_stellar_dex.buy_exchange, String::from_str(&env, "Stellar DEX"));
+        assert_eq!(via_stellar_dex.sell_price - via_stellar_dex.buy_price, 100);
+
+        // Nothing is held, so there is nothing to sell first
+        assert_eq!(client.scan_sell_first_opportunities(&Map::new(&env), &100).len(), 0);
+    }
+
+    #[test]
+    fn test_sell_first_applies_liquidity_and_confidence_floors() {
+        let (env, client) = setup_test();
+        // Selling 100 into Soroswap's bids averages 10000: 99 bps of slippage, scoring 86.
+        // Only 60 is bid at or above its 10100 quote.
+        register_mock_exchange_with_bids(&env, &client, "Soroswap", &[(10100, 60), (9850, 40)], &[]);
+        let kale = String::from_str(&env, "KALE");
+
+        let mut inventory = Map::new(&env);
+        inventory.set(String::from_str(&env, "AQUA"), 100);
+        inventory.set(kale.clone(), 100);
+        assert_eq!(client.scan_sell_first_opportunities(&inventory, &100).len(), 4);
+
+        // The high-risk asset's floor is above what selling into that book scores
+        client.set_high_risk_asset(&kale, &90);
+        let opportunities = client.scan_sell_first_opportunities(&inventory, &100);
+        assert_eq!(opportunities.len(), 2);
+        assert_eq!(opportunities.get(0).unwrap().asset, String::from_str(&env, "AQUA"));
+        assert_eq!(opportunities.get(1).unwrap().asset, String::from_str(&env, "AQUA"));
+
+        // Neither asset finds enough bid depth at the quoted price
+        client.set_min_liquidity(&100);
+        assert_eq!(client.scan_sell_first_opportunities(&inventory, &100).len(), 0);
+    }
+
+    #[test]
+    fn test_scan_exchanges_configurable() {
+        let (env, client) = setup_test();
+        let stellar_dex = String::from_str(&env, "Stellar DEX");
+        let soroswap = String::from_str(&env, "Soroswap");
+        let phoenix = String::from_str(&env, "Phoenix");
+        assert_eq!(client.get_scan_exchanges().len(), 3);
+
+        // Built-in venues can be selected without an adapter, each once, so narrowing the set
+        // leaves their quotes, slippage and confidence as they were
+        client.set_scan_exchanges(&vec![&env, stellar_dex.clone(), soroswap.clone(), stellar_dex.clone()]);
+        assert_eq!(client.get_scan_exchanges(), vec![&env, stellar_dex.clone(), soroswap.clone()]);
+
+        // With Aqua Network dropped, the only pair left is Stellar DEX against Soroswap
+        let opportunities = client.scan_opportunities(&assets(&env), &0);
+        assert_eq!(opportunities.len(), 1);
+        let opportunity = opportunities.get(0).unwrap();
+        assert_eq!(opportunity.buy_exchange, stellar_dex);
+        assert_eq!(opportunity.sell_exchange, soroswap);
+        assert_eq!((opportunity.estimated_slippage_bps, opportunity.confidence_score), (0, 95));
+
+        // Any other venue needs an adapter to quote it
+        let result = client.try_set_scan_exchanges(&vec![&env, stellar_dex.clone(), phoenix.clone()]);
+        assert_eq!(result, Err(Ok(ArbitrageError::UnknownExchange)));
+
+        // Once registered, Phoenix is quoted at its book's 10200 mid and paired with Stellar DEX
+        register_mock_exchange_with_bids(&env, &client, "Phoenix", &[(10150, 1000000)], &[(10250, 1000000)]);
+        client.set_scan_exchanges(&vec![&env, stellar_dex.clone(), phoenix.clone()]);
+        let opportunities = client.scan_opportunities(&assets(&env), &0);
+        assert_eq!(opportunities.len(), 1);
+        let opportunity = opportunities.get(0).unwrap();
+        assert_eq!(opportunity.buy_exchange, stellar_dex);
+        assert_eq!(opportunity.sell_exchange, phoenix);
+        assert_eq!((opportunity.buy_price, opportunity.sell_price), (10000, 10200));
+    }
+}
@@ -1,4 +1,4 @@
-use soroban_sdk::{contracttype, Vec, Env};
+use soroban_sdk::{contracttype, Vec};
 
 #[contracttype]
 #[derive(Clone)]
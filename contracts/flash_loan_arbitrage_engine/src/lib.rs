@@ -1,5 +1,8 @@
 #![no_std]
-use soroban_sdk::{contract, contractimpl, contracttype, contracterror, contractclient, Env, String, Address, Vec, Map, Bytes};
+// Entry points take their whole request as arguments, as the contract ABI exposes them
+#![allow(clippy::too_many_arguments)]
+use soroban_sdk::{contract, contractimpl, contracttype, contracterror, contractclient, token, vec, Env, IntoVal, String, Symbol, Address, Vec, Map, Bytes, BytesN, Val};
+use soroban_sdk::auth::{ContractContext, InvokerContractAuthEntry, SubContractInvocation};
 
 #[contracttype]
 pub struct FlashLoanParams {
@@ -10,6 +13,7 @@ pub struct FlashLoanParams {
 }
 
 #[contracttype]
+#[derive(Clone)]
 pub struct ArbitrageTrade {
     pub buy_exchange: Address,
     pub sell_exchange: Address,
@@ -20,15 +24,57 @@ pub struct ArbitrageTrade {
 }
 
 #[contracttype]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FlashLoanResult {
+    pub trade_id: String,
     pub success: bool,
     pub profit: i128,
+    pub profit_bps: i128, // Profit relative to the borrowed amount
+    pub accounting_profit: Option<i128>, // Profit in the accounting currency, when an accounting oracle is set
     pub timestamp: u64,
     pub error_message: String,
 }
 
+#[contracttype]
+#[derive(Clone)]
+pub struct RiskParameters {
+    pub max_gas_price: i128,
+    pub max_hops: u32,
+}
+
+#[contracttype]
+pub struct Config {
+    pub admin: Address,
+    pub operator: Address,
+    pub risk_parameters: RiskParameters,
+    pub flash_loan_fee_bps: i128,
+    pub observe_only: bool,
+    pub round_up_fees: bool,
+    pub allowed_provider_count: u32,
+    pub accounting_oracle: Option<Address>,
+    pub min_profit_bps: i128,
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub enum DataKey {
+    Admin,
+    Operator,
+    RiskParameters,
+    TradeCounter,
+    ObserveOnly,
+    AllowedProviders,
+    RoundUpFees,
+    FailureCooldown,
+    AccountingOracle,
+    MinProfitBps,
+    IdempotentResult(BytesN<32>),
+    AssetCooldownUntil(Address),
+    RetainedProfit(Address),
+}
+
 #[contracterror]
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum FlashLoanError {
     InvalidFlashLoanProvider = 1,
     InsufficientProfit = 2,
@@ -36,6 +82,36 @@ pub enum FlashLoanError {
     ArbitrageExecutionFailed = 4,
     RepaymentFailed = 5,
     InvalidParameters = 6,
+    AlreadyInitialized = 7,
+    NotInitialized = 8,
+    RiskLimitExceeded = 9,
+    AssetCoolingDown = 10,
+}
+
+// Default ceiling on the network fee an arbitrage run is allowed to pay
+const DEFAULT_MAX_GAS_PRICE: i128 = 100000;
+
+// Default cap on the number of legs in one arbitrage route
+const DEFAULT_MAX_HOPS: u32 = 3;
+
+// Flash loan provider fee, in basis points
+const FLASH_LOAN_FEE_BPS: i128 = 9;
+
+// Reflector price record, as returned by the oracle's get_price
+#[contracttype]
+#[derive(Clone)]
+pub struct OraclePriceData {
+    pub price: i128,
+    pub timestamp: u64,
+    pub confidence: u32,
+    pub volume_24h: i128,
+}
+
+// Interface for a price oracle quoting assets in the accounting currency
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    fn get_price(asset_address: Address) -> OraclePriceData;
+    fn get_oracle_decimals() -> u32;
 }
 
 // Interface for a flash loan provider contract
@@ -49,10 +125,40 @@ pub trait FlashLoanProvider {
     ) -> bool;
 }
 
+// Opportunity record, as returned by the arbitrage detector's scan_opportunities
+#[contracttype]
+#[derive(Clone)]
+pub struct ArbitrageOpportunity {
+    pub asset: String,
+    pub buy_exchange: String,
+    pub sell_exchange: String,
+    pub buy_price: i128,
+    pub sell_price: i128,
+    pub available_amount: i128,
+    pub estimated_profit: i128,
+    pub confidence_score: i128,
+    pub estimated_slippage_bps: i128,
+    pub expiry_time: u64,
+}
+
+// Execution report, as returned by the trading engine's buy and sell orders
+#[contracttype]
+#[derive(Clone)]
+pub struct TradeResult {
+    pub success: bool,
+    pub executed_amount: i64,
+    pub average_price: i64,
+    pub slippage_bps: i64,
+    pub fees_paid: i64,
+    pub timestamp: u64,
+    pub error_message: String,
+    pub pending_order_id: Option<u64>,
+}
+
 // Interface for the arbitrage detector contract
 #[contractclient(name = "ArbitrageDetectorClient")]
 pub trait ArbitrageDetector {
-    fn scan_opportunities(assets: Vec<String>, min_profit: i128) -> Vec<super::arbitrage_detector::ArbitrageOpportunity>;
+    fn scan_opportunities(assets: Vec<String>, min_profit: i128) -> Vec<ArbitrageOpportunity>;
 }
 
 // Interface for the trading engine contract
@@ -63,20 +169,20 @@ pub trait TradingEngine {
         dex_contract: Address,
         payment_asset: Address,
         target_asset: Address,
-        amount_to_buy: i128,
-        max_payment_amount: i128,
+        amount_to_buy: i64,
+        max_payment_amount: i64,
         deadline: u64,
-    ) -> super::trading_engine::TradeResult;
-    
+    ) -> TradeResult;
+
     fn execute_sell_order(
         trader: Address,
         dex_contract: Address,
         target_asset: Address,
         payment_asset: Address,
-        amount_to_sell: i128,
-        min_payment_amount: i128,
+        amount_to_sell: i64,
+        min_payment_amount: i64,
         deadline: u64,
-    ) -> super::trading_engine::TradeResult;
+    ) -> TradeResult;
 }
 
 #[contract]
@@ -84,7 +190,144 @@ pub struct FlashLoanArbitrageEngine;
 
 #[contractimpl]
 impl FlashLoanArbitrageEngine {
-    /// Execute a flash loan arbitrage trade
+    /// Initialize the engine with an admin and default risk parameters
+    pub fn initialize(env: Env, admin: Address) -> Result<(), FlashLoanError> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(FlashLoanError::AlreadyInitialized);
+        }
+
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::RiskParameters, &RiskParameters {
+            max_gas_price: DEFAULT_MAX_GAS_PRICE,
+            max_hops: DEFAULT_MAX_HOPS,
+        });
+        Ok(())
+    }
+
+    /// Update the risk parameters (admin only)
+    pub fn set_risk_parameters(env: Env, params: RiskParameters) -> Result<(), FlashLoanError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::RiskParameters, &params);
+        Ok(())
+    }
+
+    /// Get the current risk parameters
+    pub fn get_risk_parameters(env: Env) -> Result<RiskParameters, FlashLoanError> {
+        env.storage()
+            .instance()
+            .get(&DataKey::RiskParameters)
+            .ok_or(FlashLoanError::NotInitialized)
+    }
+
+    /// Get a snapshot of every engine setting in one read
+    pub fn get_config(env: Env) -> Result<Config, FlashLoanError> {
+        let admin: Address = env.storage()
+            .instance()
+            .get(&DataKey::Admin)
+            .ok_or(FlashLoanError::NotInitialized)?;
+
+        Ok(Config {
+            operator: env.storage().instance().get(&DataKey::Operator).unwrap_or(admin.clone()),
+            admin,
+            risk_parameters: Self::get_risk_parameters(env.clone())?,
+            flash_loan_fee_bps: FLASH_LOAN_FEE_BPS,
+            observe_only: Self::is_observe_only(env.clone()),
+            round_up_fees: env.storage().instance().get(&DataKey::RoundUpFees).unwrap_or(false),
+            allowed_provider_count: Self::get_allowed_providers(env.clone()).len(),
+            accounting_oracle: env.storage().instance().get(&DataKey::AccountingOracle),
+            min_profit_bps: env.storage().instance().get(&DataKey::MinProfitBps).unwrap_or(0),
+        })
+    }
+
+    /// Round fees up instead of truncating them, so fee and profit math never favours
+    /// the trader over the protocol (admin only)
+    pub fn set_round_up_fees(env: Env, round_up_fees: bool) -> Result<(), FlashLoanError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::RoundUpFees, &round_up_fees);
+        Ok(())
+    }
+
+    /// Set the account allowed to execute flash loan arbitrage; the admin until set (admin only)
+    pub fn set_operator(env: Env, operator: Address) -> Result<(), FlashLoanError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::Operator, &operator);
+        Ok(())
+    }
+
+    /// Set how long, in seconds, an asset is blocked after a failed flash loan; 0 disables it (admin only)
+    pub fn set_failure_cooldown(env: Env, seconds: u64) -> Result<(), FlashLoanError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::FailureCooldown, &seconds);
+        Ok(())
+    }
+
+    /// Require routes to expect at least `min_profit_bps` of the borrowed amount, net of the
+    /// loan fee, whenever that is more than the caller's absolute min_profit; 0 disables it (admin only)
+    pub fn set_min_profit_bps(env: Env, min_profit_bps: i128) -> Result<(), FlashLoanError> {
+        Self::require_admin(&env)?;
+        if min_profit_bps < 0 {
+            return Err(FlashLoanError::InvalidParameters);
+        }
+        env.storage().instance().set(&DataKey::MinProfitBps, &min_profit_bps);
+        Ok(())
+    }
+
+    /// Set the oracle whose quotes define the accounting currency profits are also reported in (admin only)
+    pub fn set_accounting_oracle(env: Env, oracle: Address) -> Result<(), FlashLoanError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::AccountingOracle, &oracle);
+        Ok(())
+    }
+
+    /// Allow a flash loan provider to be used for execution (admin only)
+    pub fn add_flash_loan_provider(env: Env, provider: Address) -> Result<(), FlashLoanError> {
+        Self::require_admin(&env)?;
+        let mut providers = Self::get_allowed_providers(env.clone());
+        if !providers.contains(&provider) {
+            providers.push_back(provider);
+            env.storage().instance().set(&DataKey::AllowedProviders, &providers);
+        }
+        Ok(())
+    }
+
+    /// Remove a flash loan provider from the allowlist (admin only)
+    pub fn remove_flash_loan_provider(env: Env, provider: Address) -> Result<(), FlashLoanError> {
+        Self::require_admin(&env)?;
+        let mut providers = Self::get_allowed_providers(env.clone());
+        if let Some(index) = providers.first_index_of(&provider) {
+            providers.remove(index);
+            env.storage().instance().set(&DataKey::AllowedProviders, &providers);
+        }
+        Ok(())
+    }
+
+    /// Get the allowlisted flash loan providers
+    pub fn get_allowed_providers(env: Env) -> Vec<Address> {
+        env.storage().instance().get(&DataKey::AllowedProviders).unwrap_or(Vec::new(&env))
+    }
+
+    /// Enable or disable observe-only mode (admin only).
+    /// In observe-only mode executions are fully evaluated but never borrow.
+    pub fn set_observe_only(env: Env, enabled: bool) -> Result<(), FlashLoanError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::ObserveOnly, &enabled);
+        Ok(())
+    }
+
+    /// Check whether observe-only mode is enabled
+    pub fn is_observe_only(env: Env) -> bool {
+        env.storage().instance().get(&DataKey::ObserveOnly).unwrap_or(false)
+    }
+
+    /// Generate a unique trade id, increasing with every call
+    pub fn generate_trade_id(env: Env) -> String {
+        Self::next_trade_id(&env)
+    }
+
+    /// Execute a flash loan arbitrage trade.
+    /// Retries carrying the `idempotency_key` of a live run that succeeded get the cached result
+    /// instead of re-executing. Failed and observe-only runs aren't cached, so they can be retried.
+    /// Only the operator may execute, so nobody else can trip an asset's failure cooldown.
     pub fn execute_flash_loan_arbitrage(
         env: Env,
         flash_loan_provider: Address,
@@ -93,69 +336,81 @@ impl FlashLoanArbitrageEngine {
         arbitrage_trades: Vec<ArbitrageTrade>,
         min_profit: i128,
         deadline: u64,
+        current_gas_price: i128,
+        idempotency_key: Option<BytesN<32>>,
     ) -> Result<FlashLoanResult, FlashLoanError> {
-        // Validate parameters
-        if amount <= 0 || min_profit <= 0 || deadline <= env.ledger().timestamp() {
-            return Err(FlashLoanError::InvalidParameters);
+        Self::require_operator(&env)?;
+
+        if let Some(key) = &idempotency_key {
+            if let Some(result) = env.storage().persistent().get(&DataKey::IdempotentResult(key.clone())) {
+                return Ok(result);
+            }
         }
 
-        // Create flash loan parameters
-        let params = FlashLoanParams {
+        let result = Self::run_flash_loan_arbitrage(
+            env.clone(),
+            flash_loan_provider,
             asset,
             amount,
-            fee: amount * 9 / 10000, // 0.09% fee
+            arbitrage_trades,
+            min_profit,
             deadline,
-        };
-
-        // Serialize arbitrage trades for the callback
-        let mut data_map = Map::new(&env);
-        data_map.set(String::from_str(&env, "trades"), arbitrage_trades);
-        data_map.set(String::from_str(&env, "min_profit"), min_profit);
-        
-        // Convert to bytes for the flash loan call
-        let data_bytes = Bytes::from_slice(&env, &[0u8; 32]); // Simplified serialization
-
-        // Call the flash loan provider
-        let flash_loan_client = FlashLoanProviderClient::new(&env, &flash_loan_provider);
-        let success = flash_loan_client.flash_loan(
-            env.current_contract_address(),
-            params.asset,
-            params.amount,
-            data_bytes,
-        );
+            current_gas_price,
+        )?;
 
-        if success {
-            // Flash loan executed successfully
-            Ok(FlashLoanResult {
-                success: true,
-                profit: 0, // Actual profit would be calculated in the callback
-                timestamp: env.ledger().timestamp(),
-                error_message: String::from_str(&env, ""),
-            })
-        } else {
-            // Flash loan failed
-            Err(FlashLoanError::RepaymentFailed)
+        if let Some(key) = idempotency_key {
+            if result.success {
+                env.storage().persistent().set(&DataKey::IdempotentResult(key), &result);
+            }
         }
+        Ok(result)
     }
 
-    /// Callback function called by the flash loan provider
+    /// Callback function called by the flash loan provider. Only checks that the loan can be
+    /// repaid; the provider pulls the repayment under the authorization the run gives it, and
+    /// the run then measures and records the profit.
     pub fn flash_loan_callback(
         env: Env,
         sender: Address,
         asset: Address,
         amount: i128,
         fee: i128,
-        data: Bytes,
+        _data: Bytes,
     ) -> Result<bool, FlashLoanError> {
-        // Verify that the sender is the flash loan provider
-        
-        // Deserialize the arbitrage trades from data
-        
-        // Execute arbitrage trades
-        let profit = amount - fee; // Simplified profit calculation
-        
-        // If profit is positive, the flash loan was successful
-        Ok(profit > 0)
+        // Verify that the sender is an allowed flash loan provider
+        if !Self::get_allowed_providers(env.clone()).contains(&sender) {
+            return Err(FlashLoanError::InvalidFlashLoanProvider);
+        }
+        sender.require_auth();
+
+        // The provider pulls amount + fee once we return, so it must already be on hand.
+        // Profit retained from earlier loans can't be spent on repaying this one.
+        let retained = Self::get_retained_profit(env.clone(), asset.clone());
+        Self::verify_repayment(env, asset, retained + amount + fee)
+    }
+
+    /// Check that the contract holds enough of `asset` to repay `expected`
+    pub fn verify_repayment(env: Env, asset: Address, expected: i128) -> Result<bool, FlashLoanError> {
+        let balance = token::TokenClient::new(&env, &asset).balance(&env.current_contract_address());
+        if balance < expected {
+            return Err(FlashLoanError::RepaymentFailed);
+        }
+        Ok(true)
+    }
+
+    /// Net profit in `asset` kept by the engine after repaying its flash loans
+    pub fn get_retained_profit(env: Env, asset: Address) -> i128 {
+        env.storage().instance().get(&DataKey::RetainedProfit(asset)).unwrap_or(0)
+    }
+
+    /// Current balance of each token held by this contract, for reconciliation
+    pub fn get_balances(env: Env, tokens: Vec<Address>) -> Map<Address, i128> {
+        let mut balances = Map::new(&env);
+        for asset in tokens.iter() {
+            let balance = token::TokenClient::new(&env, &asset).balance(&env.current_contract_address());
+            balances.set(asset, balance);
+        }
+        balances
     }
 
     /// Calculate the maximum profitable amount for a given arbitrage opportunity
@@ -172,7 +427,7 @@ impl FlashLoanArbitrageEngine {
         }
         
         let price_difference = sell_price - buy_price;
-        let fee_amount = (price_difference * fee_rate) / 10000;
+        let fee_amount = Self::bps_fee(&env, price_difference, fee_rate);
         let net_profit_per_unit = price_difference - fee_amount;
         
         if net_profit_per_unit <= gas_cost {
@@ -182,4 +437,1030 @@ impl FlashLoanArbitrageEngine {
         // Simplified 
         1000000000 // Return a fixed amount for demonstration
     }
-}
\ No newline at end of file
+
+    /// Build a trade id of the form TRADE-<timestamp>-<counter>.
+    /// Both numbers are zero-padded so ids sort in the order they were issued.
+    fn next_trade_id(env: &Env) -> String {
+        let counter: u64 = env.storage().instance().get(&DataKey::TradeCounter).unwrap_or(0) + 1;
+        env.storage().instance().set(&DataKey::TradeCounter, &counter);
+
+        let mut buf = [0u8; 47];
+        buf[..6].copy_from_slice(b"TRADE-");
+        Self::write_zero_padded(&mut buf[6..26], env.ledger().timestamp());
+        buf[26] = b'-';
+        Self::write_zero_padded(&mut buf[27..], counter);
+        String::from_bytes(env, &buf)
+    }
+
+    /// Write `value` as decimal digits filling the whole of `out`
+    fn write_zero_padded(out: &mut [u8], mut value: u64) {
+        for digit in out.iter_mut().rev() {
+            *digit = b'0' + (value % 10) as u8;
+            value /= 10;
+        }
+    }
+
+    /// Validate and execute a flash loan arbitrage run
+    fn run_flash_loan_arbitrage(
+        env: Env,
+        flash_loan_provider: Address,
+        asset: Address,
+        amount: i128,
+        arbitrage_trades: Vec<ArbitrageTrade>,
+        min_profit: i128,
+        deadline: u64,
+        current_gas_price: i128,
+    ) -> Result<FlashLoanResult, FlashLoanError> {
+        // Validate parameters
+        if amount <= 0 || min_profit <= 0 || deadline <= env.ledger().timestamp() {
+            return Err(FlashLoanError::InvalidParameters);
+        }
+
+        if !Self::get_allowed_providers(env.clone()).contains(&flash_loan_provider) {
+            return Err(FlashLoanError::InvalidFlashLoanProvider);
+        }
+
+        // Refuse to execute while network fees are above the configured ceiling
+        let risk_params = Self::get_risk_parameters(env.clone())?;
+        if current_gas_price > risk_params.max_gas_price {
+            return Err(FlashLoanError::RiskLimitExceeded);
+        }
+
+        Self::validate_route(&arbitrage_trades, risk_params.max_hops)?;
+
+        // Don't keep retrying a market that just failed
+        let cooldown_until: u64 = env.storage().instance().get(&DataKey::AssetCooldownUntil(asset.clone())).unwrap_or(0);
+        if env.ledger().timestamp() < cooldown_until {
+            return Err(FlashLoanError::AssetCoolingDown);
+        }
+
+        // Create flash loan parameters
+        let params = FlashLoanParams {
+            asset,
+            amount,
+            fee: Self::bps_fee(&env, amount, FLASH_LOAN_FEE_BPS),
+            deadline,
+        };
+
+        let mut expected_profit = 0;
+        for trade in arbitrage_trades.iter() {
+            expected_profit += trade.expected_profit;
+        }
+
+        // The route has to clear min_profit net of the loan fee. With a relative floor
+        // configured, the required profit scales with the borrowed amount when that is higher.
+        let min_profit_bps: i128 = env.storage().instance().get(&DataKey::MinProfitBps).unwrap_or(0);
+        let min_profit = min_profit.max(Self::bps_fee(&env, params.amount, min_profit_bps));
+        if expected_profit - params.fee < min_profit {
+            return Err(FlashLoanError::InsufficientProfit);
+        }
+
+        // In observe-only mode, report the expected outcome without calling the provider
+        if Self::is_observe_only(env.clone()) {
+            let profit = expected_profit - params.fee;
+            return Ok(FlashLoanResult {
+                trade_id: Self::next_trade_id(&env),
+                success: false,
+                profit,
+                profit_bps: Self::profit_bps(&env, profit, params.amount),
+                accounting_profit: Self::to_accounting_currency(&env, &params.asset, profit),
+                timestamp: env.ledger().timestamp(),
+                error_message: String::from_str(&env, "observe_only"),
+            });
+        }
+
+        // Serialize arbitrage trades for the callback
+        let mut data_map: Map<String, Val> = Map::new(&env);
+        data_map.set(String::from_str(&env, "trades"), arbitrage_trades.into_val(&env));
+        data_map.set(String::from_str(&env, "min_profit"), min_profit.into_val(&env));
+        
+        // Convert to bytes for the flash loan call
+        let data_bytes = Bytes::from_slice(&env, &[0u8; 32]); // Simplified serialization
+
+        let engine = env.current_contract_address();
+        let asset_client = token::TokenClient::new(&env, &params.asset);
+        let balance_before = asset_client.balance(&engine);
+
+        // The provider pulls amount + fee back from the engine through the token. That transfer
+        // is a call the provider makes, so the engine has to authorize it up front. This covers
+        // only the engine's next call, so nothing may be called before the provider.
+        env.authorize_as_current_contract(vec![
+            &env,
+            InvokerContractAuthEntry::Contract(SubContractInvocation {
+                context: ContractContext {
+                    contract: params.asset.clone(),
+                    fn_name: Symbol::new(&env, "transfer"),
+                    args: (engine.clone(), flash_loan_provider.clone(), params.amount + params.fee).into_val(&env),
+                },
+                sub_invocations: Vec::new(&env),
+            }),
+        ]);
+
+        // Call the flash loan provider. A provider that fails outright, e.g. because the
+        // repayment couldn't be made, is rolled back and treated like one declining the loan.
+        let flash_loan_client = FlashLoanProviderClient::new(&env, &flash_loan_provider);
+        let success = matches!(
+            flash_loan_client.try_flash_loan(&engine, &params.asset, &params.amount, &data_bytes),
+            Ok(Ok(true))
+        );
+
+        if success {
+            // The provider has been repaid, so whatever the run added to the balance is profit.
+            // A loss is paid out of profit retained from earlier runs.
+            let profit = asset_client.balance(&engine) - balance_before;
+            let retained_key = DataKey::RetainedProfit(params.asset.clone());
+            let retained: i128 = env.storage().instance().get(&retained_key).unwrap_or(0);
+            env.storage().instance().set(&retained_key, &(retained + profit).max(0));
+
+            Ok(FlashLoanResult {
+                trade_id: Self::next_trade_id(&env),
+                success: true,
+                profit,
+                profit_bps: Self::profit_bps(&env, profit, params.amount),
+                accounting_profit: Self::to_accounting_currency(&env, &params.asset, profit),
+                timestamp: env.ledger().timestamp(),
+                error_message: String::from_str(&env, ""),
+            })
+        } else {
+            // Flash loan failed. This is reported as a result rather than an error so the
+            // cooldown written here isn't rolled back with the rest of the invocation.
+            let cooldown: u64 = env.storage().instance().get(&DataKey::FailureCooldown).unwrap_or(0);
+            if cooldown > 0 {
+                env.storage().instance().set(
+                    &DataKey::AssetCooldownUntil(params.asset.clone()),
+                    &(env.ledger().timestamp() + cooldown),
+                );
+            }
+
+            Ok(FlashLoanResult {
+                trade_id: Self::next_trade_id(&env),
+                success: false,
+                profit: 0,
+                profit_bps: 0,
+                accounting_profit: Self::to_accounting_currency(&env, &params.asset, 0),
+                timestamp: env.ledger().timestamp(),
+                error_message: String::from_str(&env, "flash_loan_failed"),
+            })
+        }
+    }
+
+    /// Convert an amount of `asset` into the accounting currency at the current oracle price,
+    /// if an accounting oracle is configured and answers. A failing oracle only costs the
+    /// conversion, never the run.
+    fn to_accounting_currency(env: &Env, asset: &Address, amount: i128) -> Option<i128> {
+        let oracle: Address = env.storage().instance().get(&DataKey::AccountingOracle)?;
+        if amount == 0 {
+            return Some(0);
+        }
+        let oracle_client = PriceOracleClient::new(env, &oracle);
+        let price = match oracle_client.try_get_price(asset) {
+            Ok(Ok(data)) => data.price,
+            _ => return None,
+        };
+        let price_scale = match oracle_client.try_get_oracle_decimals() {
+            Ok(Ok(decimals)) => 10i128.checked_pow(decimals)?,
+            _ => return None,
+        };
+        Some(amount * price / price_scale)
+    }
+
+    /// Fee of `bps` basis points on `amount`, rounded up when round_up_fees is set.
+    /// Required profit floors round up the same way.
+    fn bps_fee(env: &Env, amount: i128, bps: i128) -> i128 {
+        let round_up: bool = env.storage().instance().get(&DataKey::RoundUpFees).unwrap_or(false);
+        let fee = amount * bps / 10000;
+        if round_up && fee * 10000 < amount * bps {
+            fee + 1
+        } else {
+            fee
+        }
+    }
+
+    /// `profit` in basis points of `amount`, rounded down, losses included, when round_up_fees is set
+    fn profit_bps(env: &Env, profit: i128, amount: i128) -> i128 {
+        let round_up: bool = env.storage().instance().get(&DataKey::RoundUpFees).unwrap_or(false);
+        if round_up {
+            (profit * 10000).div_euclid(amount)
+        } else {
+            profit * 10000 / amount
+        }
+    }
+
+    /// Reject routes with more than `max_hops` legs, legs that don't pick up where the previous
+    /// one left off, or that revisit an exchange. The route starts at the first leg's buy
+    /// exchange and moves to each leg's sell exchange.
+    fn validate_route(trades: &Vec<ArbitrageTrade>, max_hops: u32) -> Result<(), FlashLoanError> {
+        if trades.len() > max_hops {
+            return Err(FlashLoanError::InvalidParameters);
+        }
+
+        let first_leg = match trades.first() {
+            Some(leg) => leg,
+            None => return Ok(()),
+        };
+
+        let mut visited = Vec::new(trades.env());
+        visited.push_back(first_leg.buy_exchange);
+        for leg in trades.iter() {
+            if visited.last() != Some(leg.buy_exchange) || visited.contains(&leg.sell_exchange) {
+                return Err(FlashLoanError::InvalidParameters);
+            }
+            visited.push_back(leg.sell_exchange);
+        }
+        Ok(())
+    }
+
+    /// Require authorization from the stored admin
+    fn require_admin(env: &Env) -> Result<(), FlashLoanError> {
+        let admin: Address = env.storage()
+            .instance()
+            .get(&DataKey::Admin)
+            .ok_or(FlashLoanError::NotInitialized)?;
+        admin.require_auth();
+        Ok(())
+    }
+
+    /// Require authorization from the stored operator, falling back to the admin
+    fn require_operator(env: &Env) -> Result<(), FlashLoanError> {
+        let operator: Address = match env.storage().instance().get(&DataKey::Operator) {
+            Some(operator) => operator,
+            None => env.storage()
+                .instance()
+                .get(&DataKey::Admin)
+                .ok_or(FlashLoanError::NotInitialized)?,
+        };
+        operator.require_auth();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_flash_loan_arbitrage_engine {
+    use super::*;
+    use soroban_sdk::{Env, Address, BytesN, String, Vec, testutils::{Address as _, Ledger as _, MockAuth, MockAuthInvoke}};
+    use reflector_oracle_client::{testutils::register_mock_reflector, ReflectorPriceData};
+
+    fn setup_test<'a>() -> (Env, FlashLoanArbitrageEngineClient<'a>, Address) {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.ledger().with_mut(|li| {
+            li.timestamp = 12345;
+        });
+
+        let contract_id = env.register(FlashLoanArbitrageEngine, ());
+        let client = FlashLoanArbitrageEngineClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        client.initialize(&admin);
+
+        (env, client, admin)
+    }
+
+    // Provider that always reports the loan as failed
+    #[contract]
+    pub struct FailingFlashLoanProvider;
+
+    #[contractimpl]
+    impl FailingFlashLoanProvider {
+        pub fn flash_loan(_env: Env, _borrower: Address, _asset: Address, _amount: i128, _data: Bytes) -> bool {
+            false
+        }
+    }
+
+    // Provider that approves every loan and counts how often it was called. Contracts exporting
+    // the same function need their own module.
+    mod counting_provider {
+        use super::*;
+
+        #[contract]
+        pub struct CountingFlashLoanProvider;
+
+        #[contractimpl]
+        impl CountingFlashLoanProvider {
+            pub fn flash_loan(env: Env, _borrower: Address, _asset: Address, _amount: i128, _data: Bytes) -> bool {
+                let calls: u32 = env.storage().instance().get(&String::from_str(&env, "calls")).unwrap_or(0);
+                env.storage().instance().set(&String::from_str(&env, "calls"), &(calls + 1));
+                true
+            }
+
+            pub fn calls(env: Env) -> u32 {
+                env.storage().instance().get(&String::from_str(&env, "calls")).unwrap_or(0)
+            }
+        }
+    }
+    use counting_provider::{CountingFlashLoanProvider, CountingFlashLoanProviderClient};
+
+    // Provider lending real tokens and taking back the amount plus its fee once the borrower's
+    // run is done. Soroban forbids re-entry, so it can't call back into the engine: it pays out
+    // the configured proceeds along with the loan to stand in for the trades. The repayment is
+    // pulled from the borrower, so it fails, and the whole loan with it, if the borrower can't
+    // cover it or hasn't authorized it.
+    mod mock_provider {
+        use super::*;
+
+        #[contract]
+        pub struct MockFlashLoanProvider;
+
+        #[contractimpl]
+        impl MockFlashLoanProvider {
+            pub fn set_proceeds(env: Env, proceeds: i128) {
+                env.storage().instance().set(&String::from_str(&env, "proceeds"), &proceeds);
+            }
+
+            pub fn flash_loan(env: Env, borrower: Address, asset: Address, amount: i128, _data: Bytes) -> bool {
+                let proceeds: i128 = env.storage().instance().get(&String::from_str(&env, "proceeds")).unwrap_or(0);
+                let token = token::TokenClient::new(&env, &asset);
+                let provider = env.current_contract_address();
+                token.transfer(&provider, &borrower, &(amount + proceeds));
+
+                token.transfer(&borrower, &provider, &(amount + amount * FLASH_LOAN_FEE_BPS / 10000));
+                true
+            }
+        }
+    }
+    use mock_provider::{MockFlashLoanProvider, MockFlashLoanProviderClient};
+
+    fn leg(env: &Env, buy_exchange: &Address, sell_exchange: &Address) -> ArbitrageTrade {
+        ArbitrageTrade {
+            buy_exchange: buy_exchange.clone(),
+            sell_exchange: sell_exchange.clone(),
+            buy_asset: Address::generate(env),
+            sell_asset: Address::generate(env),
+            amount: 1000_0000000,
+            expected_profit: 1_0000000,
+        }
+    }
+
+    // Live run of `amount` as the admin, who operates by default, with only the admin's own
+    // authorization mocked. Anything deeper, like the provider pulling the repayment from the
+    // engine, has to be authorized by the contracts themselves.
+    fn execute_as_admin(
+        env: &Env,
+        client: &FlashLoanArbitrageEngineClient,
+        admin: &Address,
+        provider: &Address,
+        asset: &Address,
+        amount: i128,
+    ) -> FlashLoanResult {
+        let trades = profitable_route(env);
+        let deadline = env.ledger().timestamp() + 100;
+        env.mock_auths(&[MockAuth {
+            address: admin,
+            invoke: &MockAuthInvoke {
+                contract: &client.address,
+                fn_name: "execute_flash_loan_arbitrage",
+                args: (provider, asset, amount, trades.clone(), 1_0000000i128, deadline, 1000i128, None::<BytesN<32>>)
+                    .into_val(env),
+                sub_invokes: &[],
+            },
+        }]);
+        let result = client.execute_flash_loan_arbitrage(provider, asset, &amount, &trades, &1_0000000, &deadline, &1000, &None);
+        env.mock_all_auths();
+        result
+    }
+
+    // One leg expecting 2, which clears a min_profit of 1 after the 0.9 fee on borrowing 1000
+    fn profitable_route(env: &Env) -> Vec<ArbitrageTrade> {
+        vec![
+            env,
+            ArbitrageTrade {
+                expected_profit: 2_0000000,
+                ..leg(env, &Address::generate(env), &Address::generate(env))
+            },
+        ]
+    }
+
+    fn allowed_provider(env: &Env, client: &FlashLoanArbitrageEngineClient) -> Address {
+        let provider = Address::generate(env);
+        client.add_flash_loan_provider(&provider);
+        provider
+    }
+
+    #[test]
+    fn test_gas_price_above_limit_rejected() {
+        let (env, client, _admin) = setup_test();
+
+        client.set_risk_parameters(&RiskParameters {
+            max_gas_price: 50000,
+            ..client.get_risk_parameters()
+        });
+
+        let result = client.try_execute_flash_loan_arbitrage(
+            &allowed_provider(&env, &client),
+            &Address::generate(&env),
+            &1000_0000000,
+            &Vec::new(&env),
+            &1_0000000,
+            &(env.ledger().timestamp() + 100),
+            &50001,
+            &None,
+        );
+
+        assert_eq!(result, Err(Ok(FlashLoanError::RiskLimitExceeded)));
+    }
+
+    #[test]
+    fn test_get_config_reflects_setters() {
+        let (_env, client, admin) = setup_test();
+
+        client.set_risk_parameters(&RiskParameters {
+            max_gas_price: 75000,
+            ..client.get_risk_parameters()
+        });
+
+        let config = client.get_config();
+        assert_eq!(config.admin, admin);
+        assert_eq!(config.risk_parameters.max_gas_price, 75000);
+        assert_eq!(config.flash_loan_fee_bps, 9);
+    }
+
+    #[test]
+    fn test_generate_trade_id_unique_and_increasing() {
+        let (env, client, _admin) = setup_test();
+
+        let first = client.generate_trade_id();
+        let second = client.generate_trade_id();
+
+        env.ledger().with_mut(|li| {
+            li.timestamp += 10;
+        });
+        let third = client.generate_trade_id();
+
+        assert_eq!(first, String::from_str(&env, "TRADE-00000000000000012345-00000000000000000001"));
+        assert!(first != second);
+        assert!(first < second);
+        assert!(second < third);
+    }
+
+    #[test]
+    fn test_observe_only_skips_provider() {
+        let (env, client, _admin) = setup_test();
+        client.set_observe_only(&true);
+
+        let mut trades = Vec::new(&env);
+        trades.push_back(ArbitrageTrade {
+            buy_exchange: Address::generate(&env),
+            sell_exchange: Address::generate(&env),
+            buy_asset: Address::generate(&env),
+            sell_asset: Address::generate(&env),
+            amount: 1000_0000000,
+            expected_profit: 5_0000000,
+        });
+
+        // The provider is not a registered contract, so any call to it would fail
+        let result = client.execute_flash_loan_arbitrage(
+            &allowed_provider(&env, &client),
+            &Address::generate(&env),
+            &1000_0000000,
+            &trades,
+            &1_0000000,
+            &(env.ledger().timestamp() + 100),
+            &1000,
+            &None,
+        );
+
+        assert!(!result.success);
+        assert_eq!(result.error_message, String::from_str(&env, "observe_only"));
+        assert_eq!(result.profit, 5_0000000 - 1000_0000000 * 9 / 10000);
+        assert!(client.get_config().observe_only);
+    }
+
+    #[test]
+    fn test_callback_fails_on_repayment_shortfall() {
+        let (env, client, admin) = setup_test();
+        let asset = env.register_stellar_asset_contract_v2(admin).address();
+        let asset_admin = token::StellarAssetClient::new(&env, &asset);
+
+        let provider = allowed_provider(&env, &client);
+
+        // Repaying 1000 + 0.9 fee, but only 1000 is on hand
+        asset_admin.mint(&client.address, &1000_0000000);
+        let result = client.try_flash_loan_callback(
+            &provider,
+            &asset,
+            &1000_0000000,
+            &9000000,
+            &Bytes::new(&env),
+        );
+        assert_eq!(result, Err(Ok(FlashLoanError::RepaymentFailed)));
+
+        // Topping up the fee makes the loan repayable
+        asset_admin.mint(&client.address, &9000000);
+        assert!(client.verify_repayment(&asset, &1000_9000000));
+        assert!(client.flash_loan_callback(
+            &provider,
+            &asset,
+            &1000_0000000,
+            &9000000,
+            &Bytes::new(&env),
+        ));
+    }
+
+    #[test]
+    fn test_provider_allowlist() {
+        let (env, client, _admin) = setup_test();
+        let provider_a = allowed_provider(&env, &client);
+        let provider_b = allowed_provider(&env, &client);
+        let provider_c = Address::generate(&env);
+
+        let allowed = client.get_allowed_providers();
+        assert_eq!(allowed.len(), 2);
+        assert!(allowed.contains(&provider_a));
+        assert!(allowed.contains(&provider_b));
+        assert_eq!(client.get_config().allowed_provider_count, 2);
+
+        let result = client.try_execute_flash_loan_arbitrage(
+            &provider_c,
+            &Address::generate(&env),
+            &1000_0000000,
+            &Vec::new(&env),
+            &1_0000000,
+            &(env.ledger().timestamp() + 100),
+            &1000,
+            &None,
+        );
+        assert_eq!(result, Err(Ok(FlashLoanError::InvalidFlashLoanProvider)));
+
+        client.remove_flash_loan_provider(&provider_b);
+        assert_eq!(client.get_allowed_providers().len(), 1);
+    }
+
+    #[test]
+    fn test_profit_bps_matches_absolute_profit() {
+        let (env, client, _admin) = setup_test();
+        client.set_observe_only(&true);
+
+        let mut trades = Vec::new(&env);
+        trades.push_back(ArbitrageTrade {
+            buy_exchange: Address::generate(&env),
+            sell_exchange: Address::generate(&env),
+            buy_asset: Address::generate(&env),
+            sell_asset: Address::generate(&env),
+            amount: 2000_0000000,
+            expected_profit: 12_0000000,
+        });
+
+        let result = client.execute_flash_loan_arbitrage(
+            &allowed_provider(&env, &client),
+            &Address::generate(&env),
+            &2000_0000000,
+            &trades,
+            &1_0000000,
+            &(env.ledger().timestamp() + 100),
+            &1000,
+            &None,
+        );
+
+        // 12 expected minus a 1.8 loan fee is 10.2 on 2000 borrowed: 51 bps
+        assert_eq!(result.profit, 10_2000000);
+        assert_eq!(result.profit_bps, 51);
+        assert_eq!(result.profit_bps, result.profit * 10000 / 2000_0000000);
+    }
+
+    #[test]
+    fn test_route_hop_cap_and_cycles() {
+        let (env, client, _admin) = setup_test();
+        client.set_observe_only(&true);
+        let provider = allowed_provider(&env, &client);
+        let venues: [Address; 5] = core::array::from_fn(|_| Address::generate(&env));
+
+        let execute = |trades: &Vec<ArbitrageTrade>| {
+            client.try_execute_flash_loan_arbitrage(
+                &provider,
+                &Address::generate(&env),
+                &1000_0000000,
+                trades,
+                &1_0000000,
+                &(env.ledger().timestamp() + 100),
+                &1000,
+                &None,
+            )
+        };
+
+        // A 3-hop route through distinct venues is within the default cap
+        let mut route = Vec::new(&env);
+        route.push_back(leg(&env, &venues[0], &venues[1]));
+        route.push_back(leg(&env, &venues[1], &venues[2]));
+        route.push_back(leg(&env, &venues[2], &venues[3]));
+        assert!(execute(&route).is_ok());
+
+        // A fourth hop exceeds it
+        route.push_back(leg(&env, &venues[3], &venues[4]));
+        assert_eq!(execute(&route), Err(Ok(FlashLoanError::InvalidParameters)));
+
+        // Returning to the starting venue is a cycle
+        let mut route = Vec::new(&env);
+        route.push_back(leg(&env, &venues[0], &venues[1]));
+        route.push_back(leg(&env, &venues[1], &venues[0]));
+        assert_eq!(execute(&route), Err(Ok(FlashLoanError::InvalidParameters)));
+
+        // Buying again at the starting venue revisits it, even without a matching sell
+        let mut route = Vec::new(&env);
+        route.push_back(leg(&env, &venues[0], &venues[1]));
+        route.push_back(leg(&env, &venues[0], &venues[2]));
+        assert_eq!(execute(&route), Err(Ok(FlashLoanError::InvalidParameters)));
+    }
+
+    #[test]
+    fn test_round_up_fees() {
+        let (env, client, _admin) = setup_test();
+        client.set_observe_only(&true);
+        let provider = allowed_provider(&env, &client);
+
+        let mut trades = Vec::new(&env);
+        trades.push_back(leg(&env, &Address::generate(&env), &Address::generate(&env)));
+
+        // 9 bps of 1000.0000001 is 0.90000000009, which does not divide evenly
+        let execute = || {
+            client.execute_flash_loan_arbitrage(
+                &provider,
+                &Address::generate(&env),
+                &1000_0000001,
+                &trades,
+                &1,
+                &(env.ledger().timestamp() + 100),
+                &1000,
+                &None,
+            )
+        };
+
+        // Truncation understates the fee
+        assert_eq!(execute().profit, 1_0000000 - 9000000);
+
+        client.set_round_up_fees(&true);
+        assert_eq!(execute().profit, 1_0000000 - 9000001);
+    }
+
+    #[test]
+    fn test_round_up_rounds_profit_down() {
+        let (env, client, admin) = setup_test();
+        client.set_round_up_fees(&true);
+
+        // 50 bps of 100.0000001 is 0.5000000005, so the floor rounds up to 0.5000001.
+        // A route netting exactly 0.5 after the 0.0900001 fee falls short of it.
+        client.set_observe_only(&true);
+        client.set_min_profit_bps(&50);
+        let provider = allowed_provider(&env, &client);
+        let route = vec![
+            &env,
+            ArbitrageTrade {
+                expected_profit: 5900001,
+                ..leg(&env, &Address::generate(&env), &Address::generate(&env))
+            },
+        ];
+        let execute = || {
+            client.try_execute_flash_loan_arbitrage(
+                &provider,
+                &Address::generate(&env),
+                &100_0000001,
+                &route,
+                &1,
+                &(env.ledger().timestamp() + 100),
+                &1000,
+                &None,
+            )
+        };
+        assert_eq!(execute(), Err(Ok(FlashLoanError::InsufficientProfit)));
+        client.set_round_up_fees(&false);
+        assert!(execute().is_ok());
+        client.set_round_up_fees(&true);
+        client.set_min_profit_bps(&0);
+        client.set_observe_only(&false);
+
+        // Proceeds of 0.85 leave a loss of 0.05 after the 0.9 fee on 1000: -0.5 bps, which
+        // truncation would report as 0
+        let asset = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        let asset_admin = token::StellarAssetClient::new(&env, &asset);
+        let provider = env.register(MockFlashLoanProvider, ());
+        client.add_flash_loan_provider(&provider);
+        asset_admin.mint(&provider, &10000_0000000);
+        asset_admin.mint(&client.address, &1_0000000);
+        MockFlashLoanProviderClient::new(&env, &provider).set_proceeds(&8500000);
+
+        let result = execute_as_admin(&env, &client, &admin, &provider, &asset, 1000_0000000);
+        assert!(result.success);
+        assert_eq!((result.profit, result.profit_bps), (-500000, -1));
+    }
+
+    #[test]
+    fn test_failed_asset_cools_down() {
+        let (env, client, admin) = setup_test();
+        client.set_failure_cooldown(&60);
+
+        let provider = env.register(FailingFlashLoanProvider, ());
+        client.add_flash_loan_provider(&provider);
+        let asset = env.register_stellar_asset_contract_v2(admin).address();
+
+        let execute = || {
+            client.try_execute_flash_loan_arbitrage(
+                &provider,
+                &asset,
+                &1000_0000000,
+                &profitable_route(&env),
+                &1_0000000,
+                &(env.ledger().timestamp() + 100),
+                &1000,
+                &None,
+            )
+        };
+
+        let result = execute().unwrap().unwrap();
+        assert!(!result.success);
+        assert_eq!(result.error_message, String::from_str(&env, "flash_loan_failed"));
+
+        // Blocked while cooling down
+        assert_eq!(execute(), Err(Ok(FlashLoanError::AssetCoolingDown)));
+
+        // Allowed again once the cooldown has elapsed
+        env.ledger().with_mut(|li| {
+            li.timestamp += 60;
+        });
+        assert!(execute().is_ok());
+    }
+
+    #[test]
+    fn test_execution_requires_operator() {
+        let (env, client, admin) = setup_test();
+        client.set_observe_only(&true);
+        let provider = allowed_provider(&env, &client);
+
+        let execute = || {
+            client.execute_flash_loan_arbitrage(
+                &provider,
+                &Address::generate(&env),
+                &1000_0000000,
+                &profitable_route(&env),
+                &1_0000000,
+                &(env.ledger().timestamp() + 100),
+                &1000,
+                &None,
+            )
+        };
+
+        // The admin operates until an operator is set
+        execute();
+        assert_eq!(env.auths()[0].0, admin);
+
+        let operator = Address::generate(&env);
+        client.set_operator(&operator);
+        execute();
+        assert_eq!(env.auths()[0].0, operator);
+        assert_eq!(client.get_config().operator, operator);
+    }
+
+    #[test]
+    fn test_idempotency_key_returns_cached_result() {
+        let (env, client, admin) = setup_test();
+
+        let provider = env.register(CountingFlashLoanProvider, ());
+        client.add_flash_loan_provider(&provider);
+        let provider_client = CountingFlashLoanProviderClient::new(&env, &provider);
+        let asset = env.register_stellar_asset_contract_v2(admin).address();
+        let key = Some(BytesN::from_array(&env, &[7u8; 32]));
+
+        let execute = || {
+            client.execute_flash_loan_arbitrage(
+                &provider,
+                &asset,
+                &1000_0000000,
+                &profitable_route(&env),
+                &1_0000000,
+                &(env.ledger().timestamp() + 100),
+                &1000,
+                &key,
+            )
+        };
+
+        // An observe-only run under the key doesn't stop the live run
+        client.set_observe_only(&true);
+        assert!(!execute().success);
+        client.set_observe_only(&false);
+
+        let first = execute();
+        let second = execute();
+
+        assert!(first.success);
+        assert_eq!(first, second);
+        assert_eq!(provider_client.calls(), 1);
+    }
+
+    #[test]
+    fn test_idempotency_key_not_cached_on_failure() {
+        let (env, client, admin) = setup_test();
+
+        let provider = env.register(FailingFlashLoanProvider, ());
+        client.add_flash_loan_provider(&provider);
+        let asset = env.register_stellar_asset_contract_v2(admin).address();
+        let key = Some(BytesN::from_array(&env, &[7u8; 32]));
+
+        let execute = |provider: &Address| {
+            client.execute_flash_loan_arbitrage(
+                provider,
+                &asset,
+                &1000_0000000,
+                &profitable_route(&env),
+                &1_0000000,
+                &(env.ledger().timestamp() + 100),
+                &1000,
+                &key,
+            )
+        };
+
+        assert!(!execute(&provider).success);
+
+        // A retry under the same key runs again rather than replaying the failure
+        let working_provider = env.register(CountingFlashLoanProvider, ());
+        client.add_flash_loan_provider(&working_provider);
+        assert!(execute(&working_provider).success);
+        assert_eq!(CountingFlashLoanProviderClient::new(&env, &working_provider).calls(), 1);
+    }
+
+    #[test]
+    fn test_get_balances() {
+        let (env, client, admin) = setup_test();
+        let usdc = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        let aqua = env.register_stellar_asset_contract_v2(admin).address();
+        token::StellarAssetClient::new(&env, &usdc).mint(&client.address, &250_0000000);
+        token::StellarAssetClient::new(&env, &aqua).mint(&client.address, &1200_0000000);
+
+        let balances = client.get_balances(&Vec::from_array(&env, [usdc.clone(), aqua.clone()]));
+        assert_eq!(balances.len(), 2);
+        assert_eq!(balances.get(usdc), Some(250_0000000));
+        assert_eq!(balances.get(aqua), Some(1200_0000000));
+    }
+
+    #[test]
+    fn test_profit_reported_in_accounting_currency() {
+        let (env, client, _admin) = setup_test();
+        client.set_observe_only(&true);
+        let provider = allowed_provider(&env, &client);
+
+        let mut trades = Vec::new(&env);
+        trades.push_back(ArbitrageTrade {
+            expected_profit: 5_0000000,
+            ..leg(&env, &Address::generate(&env), &Address::generate(&env))
+        });
+        let execute = || {
+            client.execute_flash_loan_arbitrage(
+                &provider,
+                &Address::generate(&env),
+                &1000_0000000,
+                &trades,
+                &1_0000000,
+                &(env.ledger().timestamp() + 100),
+                &1000,
+                &None,
+            )
+        };
+
+        // Without an accounting oracle only the native profit is reported
+        let result = execute();
+        assert_eq!(result.profit, 4_1000000);
+        assert_eq!(result.accounting_profit, None);
+
+        // An oracle that doesn't answer leaves the profit unconverted
+        client.set_accounting_oracle(&Address::generate(&env));
+        assert_eq!(execute().accounting_profit, None);
+
+        // 4.1 of the borrowed asset at 0.25 each, quoted with the oracle's 14 decimals
+        let reflector = register_mock_reflector(&env);
+        reflector.set_price(&ReflectorPriceData {
+            price: 25000000000000,
+            timestamp: env.ledger().timestamp(),
+            confidence: 95,
+            volume_24h: 1000000_0000000,
+        });
+        client.set_accounting_oracle(&reflector.address);
+        let result = execute();
+        assert_eq!(result.profit, 4_1000000);
+        assert_eq!(result.accounting_profit, Some(1_0250000));
+        assert_eq!(client.get_config().accounting_oracle, Some(reflector.address));
+    }
+
+    #[test]
+    fn test_zero_profit_skips_accounting_oracle() {
+        let (env, client, admin) = setup_test();
+        let provider = env.register(FailingFlashLoanProvider, ());
+        client.add_flash_loan_provider(&provider);
+
+        // Nothing answers at this address, but a zero profit needs no price
+        client.set_accounting_oracle(&Address::generate(&env));
+        let result = client.execute_flash_loan_arbitrage(
+            &provider,
+            &env.register_stellar_asset_contract_v2(admin).address(),
+            &1000_0000000,
+            &profitable_route(&env),
+            &1_0000000,
+            &(env.ledger().timestamp() + 100),
+            &1000,
+            &None,
+        );
+        assert!(!result.success);
+        assert_eq!(result.accounting_profit, Some(0));
+    }
+
+    #[test]
+    fn test_min_profit_bps_scales_with_amount() {
+        let (env, client, _admin) = setup_test();
+        client.set_observe_only(&true);
+        client.set_min_profit_bps(&50);
+        let provider = allowed_provider(&env, &client);
+
+        // Borrowing 100 requires 0.5 profit after the 0.09 loan fee
+        let execute = |expected_profit: i128, min_profit: i128| {
+            let mut trades = Vec::new(&env);
+            trades.push_back(ArbitrageTrade {
+                expected_profit,
+                ..leg(&env, &Address::generate(&env), &Address::generate(&env))
+            });
+            client.try_execute_flash_loan_arbitrage(
+                &provider,
+                &Address::generate(&env),
+                &100_0000000,
+                &trades,
+                &min_profit,
+                &(env.ledger().timestamp() + 100),
+                &1000,
+                &None,
+            )
+        };
+
+        assert_eq!(execute(5000000, 1), Err(Ok(FlashLoanError::InsufficientProfit)));
+
+        let result = execute(6000000, 1).unwrap().unwrap();
+        assert_eq!(result.profit, 5100000);
+        assert_eq!(client.get_config().min_profit_bps, 50);
+
+        // An absolute min_profit above the relative floor is what has to be met, also net of the fee
+        assert_eq!(execute(1_0000000, 1_0000000), Err(Ok(FlashLoanError::InsufficientProfit)));
+        assert!(execute(1_0900000, 1_0000000).is_ok());
+
+        // Without a relative floor the absolute one applies the same way
+        client.set_min_profit_bps(&0);
+        assert_eq!(execute(1_0000000, 1_0000000), Err(Ok(FlashLoanError::InsufficientProfit)));
+        assert!(execute(1_0900000, 1_0000000).is_ok());
+    }
+
+    #[test]
+    fn test_execute_against_mock_provider() {
+        let (env, client, admin) = setup_test();
+
+        let asset = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        let asset_admin = token::StellarAssetClient::new(&env, &asset);
+        let balances = token::TokenClient::new(&env, &asset);
+        let provider = env.register(MockFlashLoanProvider, ());
+        client.add_flash_loan_provider(&provider);
+        asset_admin.mint(&provider, &10000_0000000);
+
+        let execute = || execute_as_admin(&env, &client, &admin, &provider, &asset, 1000_0000000);
+
+        // Loss: nothing covers the 0.9 fee, so the loan can't be repaid
+        let result = execute();
+        assert!(!result.success);
+        assert_eq!(result.error_message, String::from_str(&env, "flash_loan_failed"));
+        assert_eq!(balances.balance(&provider), 10000_0000000);
+        assert_eq!(balances.balance(&client.address), 0);
+
+        // Profit: proceeds of 2 repay the fee and leave 1.1 with the engine
+        MockFlashLoanProviderClient::new(&env, &provider).set_proceeds(&2_0000000);
+        let result = execute();
+        assert!(result.success);
+        assert_eq!((result.profit, result.profit_bps), (1_1000000, 11));
+        assert_eq!(balances.balance(&provider), 9998_9000000);
+        assert_eq!(balances.balance(&client.address), 1_1000000);
+        assert_eq!(client.get_retained_profit(&asset), 1_1000000);
+    }
+
+    #[test]
+    fn test_callback_keeps_retained_profit() {
+        let (env, client, admin) = setup_test();
+
+        let asset = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        let asset_admin = token::StellarAssetClient::new(&env, &asset);
+        let provider = env.register(MockFlashLoanProvider, ());
+        client.add_flash_loan_provider(&provider);
+        asset_admin.mint(&provider, &10000_0000000);
+
+        // Proceeds of 10 repay 1000 + 0.9 fee and leave 9.1 behind
+        MockFlashLoanProviderClient::new(&env, &provider).set_proceeds(&10_0000000);
+        assert!(execute_as_admin(&env, &client, &admin, &provider, &asset, 1000_0000000).success);
+        assert_eq!(client.get_retained_profit(&asset), 9_1000000);
+
+        // Only allowed providers may call back
+        let callback = |sender: &Address, amount: i128| {
+            client.try_flash_loan_callback(sender, &asset, &amount, &9000000, &Bytes::new(&env))
+        };
+        assert_eq!(
+            callback(&Address::generate(&env), 1_0000000),
+            Err(Ok(FlashLoanError::InvalidFlashLoanProvider))
+        );
+
+        // The balance would cover a 1.9 repayment, but only by spending the retained profit
+        assert_eq!(callback(&provider, 1_0000000), Err(Ok(FlashLoanError::RepaymentFailed)));
+        asset_admin.mint(&client.address, &1_9000000);
+        assert_eq!(callback(&provider, 1_0000000), Ok(Ok(true)));
+
+        // The callback records nothing itself
+        assert_eq!(client.get_retained_profit(&asset), 9_1000000);
+    }
+}
@@ -2,7 +2,10 @@
 // This module handles communication with the Reflector Network oracle
 // to fetch real-time price data for arbitrage opportunities
 #![no_std]
-use soroban_sdk::{contract, contractimpl, contractclient, contracterror, contracttype, Env, String, Address, Vec, symbol_short};
+use soroban_sdk::{contract, contractimpl, contractclient, contracterror, contracttype, Env, String, Address, Vec};
+
+#[cfg(any(test, feature = "testutils"))]
+pub mod testutils;
 
 // Reflector Price Data structure
 #[contracttype]
@@ -15,7 +18,7 @@ pub struct ReflectorPriceData {
 }
 
 #[contracttype]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PriceData {
     pub asset: String,
     pub price: i128,
@@ -32,6 +35,7 @@ pub struct HistoricalPrice {
     pub price: i128,
     pub timestamp: u64,
     pub volume: i128,
+    pub confidence: u32,
 }
 
 #[contracterror]
@@ -44,32 +48,73 @@ pub enum OracleError {
     ContractCallFailed = 4,
     UnsupportedAsset = 5,
     InvalidWindow = 6,
+    AlreadyInitialized = 7,
+    NotInitialized = 8,
+    StalePrice = 9,
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub enum DataKey {
+    Admin,
+    MinConfidence,
+    SpotMaxAge,
+    TwapMaxAge,
+    TwapFallbackPoints,
+    PriceHistory(String),
 }
 
+// Number of cached prices the local TWAP fallback averages by default
+const DEFAULT_TWAP_FALLBACK_POINTS: u32 = 10;
+
+// Upper bound on cached prices kept per asset
+const MAX_CACHED_PRICES: u32 = 100;
+
 #[contract]
 pub struct ReflectorOracleClient;
 
 #[contractimpl]
 impl ReflectorOracleClient {
-    /// Fetch real-time price and timestamp for an asset
-    pub fn get_price_and_timestamp(env: Env, asset_code: String) -> Result<(i128, u64), OracleError> {
-        // Validate asset is supported
-        if !Self::is_asset_supported(&env, asset_code.clone()) {
-            return Err(OracleError::UnsupportedAsset);
+    /// Initialize the client with an admin allowed to tune price validation
+    pub fn initialize(env: Env, admin: Address) -> Result<(), OracleError> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(OracleError::AlreadyInitialized);
         }
-        
-        // Get the Reflector contract ID
-        let reflector_contract_id = Address::from_string(&String::from_str(&env, "CBIW2BTCOMOEV5WQC2JRWVH4TAXCZNAUIUOXYVAYP4YDW4D3AEEQPNTC"));
-        let reflector_client = ReflectorPriceClient::new(&env, &reflector_contract_id);
-        
-        // Convert asset code to address
-        let asset_address = Self::asset_code_to_address(&env, asset_code);
-        
-        // Call the Reflector contract to get price data
-        match reflector_client.try_get_price(&asset_address) {
-            Ok(Ok(data)) => Ok((data.price, data.timestamp)),
-            _ => Err(OracleError::ContractCallFailed),
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        Ok(())
+    }
+
+    /// Set the minimum confidence a Reflector price must report to be used (admin only)
+    pub fn set_min_confidence(env: Env, min_confidence: u32) -> Result<(), OracleError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::MinConfidence, &min_confidence);
+        Ok(())
+    }
+
+    /// Set the oldest price, in seconds, accepted for spot reads and for the cached prices behind
+    /// the TWAP fallback. TWAP usually tolerates older data than spot; 0 disables a limit (admin only).
+    /// A spot price's confidence decays linearly from its reported value when fresh to zero at the spot limit
+    pub fn set_max_ages(env: Env, spot_max_age: u64, twap_max_age: u64) -> Result<(), OracleError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::SpotMaxAge, &spot_max_age);
+        env.storage().instance().set(&DataKey::TwapMaxAge, &twap_max_age);
+        Ok(())
+    }
+
+    /// Set how many of the most recent cached prices the local TWAP fallback averages (admin only)
+    pub fn set_twap_fallback_points(env: Env, points: u32) -> Result<(), OracleError> {
+        Self::require_admin(&env)?;
+        if points == 0 || points > MAX_CACHED_PRICES {
+            return Err(OracleError::InvalidWindow);
         }
+        env.storage().instance().set(&DataKey::TwapFallbackPoints, &points);
+        Ok(())
+    }
+
+    /// Fetch real-time price and timestamp for an asset
+    pub fn get_price_and_timestamp(env: Env, asset_code: String) -> Result<(i128, u64), OracleError> {
+        let data = Self::fetch_latest_price(&env, asset_code)?;
+        Ok((data.price, data.timestamp))
     }
 
     /// Calculate Time-Weighted Average Price over a specified window
@@ -80,7 +125,7 @@ impl ReflectorOracleClient {
         }
         
         // Validate window is reasonable (between 1 minute and 24 hours)
-        if window_seconds < 60 || window_seconds > 86400 {
+        if !(60..=86400).contains(&window_seconds) {
             return Err(OracleError::InvalidWindow);
         }
         
@@ -89,7 +134,7 @@ impl ReflectorOracleClient {
         let reflector_client = ReflectorPriceClient::new(&env, &reflector_contract_id);
         
         // Convert asset code to address
-        let asset_address = Self::asset_code_to_address(&env, asset_code);
+        let asset_address = Self::asset_code_to_address(&env, asset_code.clone());
         
         // Calculate number of records based on window (assuming 30-second intervals)
         let records = (window_seconds / 30) as u32;
@@ -97,10 +142,10 @@ impl ReflectorOracleClient {
             return Err(OracleError::InvalidWindow);
         }
         
-        // Call the Reflector contract to get TWAP price
+        // Call the Reflector contract to get TWAP price, falling back to prices cached locally
         match reflector_client.try_get_twap_price(&asset_address, &records) {
             Ok(Ok(price)) => Ok(price),
-            _ => Err(OracleError::ContractCallFailed),
+            _ => Self::cached_twap(&env, asset_code),
         }
     }
 
@@ -143,36 +188,54 @@ impl ReflectorOracleClient {
         }
     }
 
+    /// Get up to `count` prices from the local cache of fetched prices, newest first
+    pub fn get_local_history(env: Env, asset_code: String, count: u32) -> Vec<PriceData> {
+        let history: Vec<HistoricalPrice> = env.storage()
+            .persistent()
+            .get(&DataKey::PriceHistory(asset_code.clone()))
+            .unwrap_or(Vec::new(&env));
+
+        let mut prices = Vec::new(&env);
+        for entry in history.iter().rev().take(count as usize) {
+            prices.push_back(PriceData {
+                asset: asset_code.clone(),
+                price: entry.price,
+                volume_24h: entry.volume,
+                timestamp: entry.timestamp,
+                source: String::from_str(&env, "Reflector"),
+                confidence: entry.confidence as i128,
+                price_change_percentage: 0,
+            });
+        }
+        prices
+    }
+
     /// Get comprehensive price data for an asset
     pub fn get_price_data(env: Env, asset_code: String) -> Result<PriceData, OracleError> {
-        // Validate asset is supported
-        if !Self::is_asset_supported(&env, asset_code.clone()) {
-            return Err(OracleError::UnsupportedAsset);
-        }
+        let data = Self::fetch_latest_price(&env, asset_code.clone())?;
         
-        // Get the Reflector contract ID
-        let reflector_contract_id = Address::from_string(&String::from_str(&env, "CBIW2BTCOMOEV5WQC2JRWVH4TAXCZNAUIUOXYVAYP4YDW4D3AEEQPNTC"));
-        let reflector_client = ReflectorPriceClient::new(&env, &reflector_contract_id);
-        
-        // Convert asset code to address
-        let asset_address = Self::asset_code_to_address(&env, asset_code.clone());
-        
-        // Call the Reflector contract to get price data
-        match reflector_client.try_get_price(&asset_address) {
-            Ok(Ok(data)) => {
-                // Convert ReflectorPriceData to PriceData
-                Ok(PriceData {
-                    asset: asset_code,
-                    price: data.price,
-                    volume_24h: data.volume_24h,
-                    timestamp: data.timestamp,
-                    source: String::from_str(&env, "Reflector"),
-                    confidence: data.confidence as i128,
-                    price_change_percentage: 0, // This would need to be calculated separately or fetched from another method
-                })
-            },
-            _ => Err(OracleError::ContractCallFailed),
+        // Convert ReflectorPriceData to PriceData
+        Ok(PriceData {
+            asset: asset_code,
+            price: data.price,
+            volume_24h: data.volume_24h,
+            timestamp: data.timestamp,
+            source: String::from_str(&env, "Reflector"),
+            confidence: data.confidence as i128,
+            price_change_percentage: 0, // This would need to be calculated separately or fetched from another method
+        })
+    }
+
+    /// Get available liquidity for an asset, approximated by its 24h traded volume
+    pub fn get_liquidity(env: Env, asset_code: String) -> Result<i128, OracleError> {
+        let price_data = Self::get_price_data(env, asset_code)?;
+
+        // A negative volume can only come from a malformed feed
+        if price_data.volume_24h < 0 {
+            return Err(OracleError::InvalidData);
         }
+
+        Ok(price_data.volume_24h)
     }
 
     /// Get list of supported assets
@@ -209,6 +272,13 @@ impl ReflectorOracleClient {
         }
     }
 
+    /// Get the latest price for an asset together with the oracle's decimals
+    pub fn get_price_with_decimals(env: Env, asset_code: String) -> Result<(i128, u32), OracleError> {
+        let data = Self::fetch_latest_price(&env, asset_code)?;
+        let decimals = Self::get_oracle_decimals(env)?;
+        Ok((data.price, decimals))
+    }
+
     /// Get price change percentage for an asset
     pub fn get_price_change_percentage(env: Env, asset_code: String) -> Result<i128, OracleError> {
         // Validate asset is supported
@@ -240,15 +310,152 @@ impl ReflectorOracleClient {
         deviation <= max_deviation_bps
     }
 
+    /// Validate price deviation with separate limits above and below the reference price.
+    /// For a buy, a price far below the reference is usually benign while one far above is suspicious.
+    pub fn validate_directional_deviation(_env: Env, current_price: i128, reference_price: i128, max_up_bps: i128, max_down_bps: i128) -> bool {
+        if reference_price == 0 {
+            return false;
+        }
+        
+        let deviation = (current_price - reference_price) * 10000 / reference_price;
+        if deviation >= 0 {
+            deviation <= max_up_bps
+        } else {
+            -deviation <= max_down_bps
+        }
+    }
+
+    /// Helper function to fetch the latest Reflector price, rejecting quotes whose confidence,
+    /// after decaying for age, is below the minimum
+    fn fetch_latest_price(env: &Env, asset_code: String) -> Result<ReflectorPriceData, OracleError> {
+        // Validate asset is supported
+        if !Self::is_asset_supported(env, asset_code.clone()) {
+            return Err(OracleError::UnsupportedAsset);
+        }
+        
+        // Get the Reflector contract ID
+        let reflector_contract_id = Address::from_string(&String::from_str(env, "CBIW2BTCOMOEV5WQC2JRWVH4TAXCZNAUIUOXYVAYP4YDW4D3AEEQPNTC"));
+        let reflector_client = ReflectorPriceClient::new(env, &reflector_contract_id);
+        
+        // Convert asset code to address
+        let asset_address = Self::asset_code_to_address(env, asset_code.clone());
+        
+        // Call the Reflector contract to get price data
+        let mut data = match reflector_client.try_get_price(&asset_address) {
+            Ok(Ok(data)) => data,
+            _ => return Err(OracleError::ContractCallFailed),
+        };
+        
+        let spot_max_age: u64 = env.storage().instance().get(&DataKey::SpotMaxAge).unwrap_or(0);
+        if spot_max_age > 0 && env.ledger().timestamp().saturating_sub(data.timestamp) > spot_max_age {
+            return Err(OracleError::StalePrice);
+        }
+        data.confidence = Self::decay_confidence(env, data.confidence, data.timestamp);
+        
+        let min_confidence: u32 = env.storage().instance().get(&DataKey::MinConfidence).unwrap_or(0);
+        if data.confidence < min_confidence {
+            return Err(OracleError::InvalidData);
+        }
+        
+        Self::cache_price(env, asset_code, &data);
+        Ok(data)
+    }
+
+    /// Helper function to scale a confidence down by the price's age against the spot staleness limit
+    fn decay_confidence(env: &Env, confidence: u32, price_timestamp: u64) -> u32 {
+        let spot_max_age: u64 = env.storage().instance().get(&DataKey::SpotMaxAge).unwrap_or(0);
+        if spot_max_age == 0 {
+            return confidence;
+        }
+
+        let age = env.ledger().timestamp().saturating_sub(price_timestamp);
+        if age >= spot_max_age {
+            return 0;
+        }
+        (confidence as u64 * (spot_max_age - age) / spot_max_age) as u32
+    }
+
+    /// Helper function to append a fetched price to the asset's history, skipping repeat reads
+    fn cache_price(env: &Env, asset_code: String, data: &ReflectorPriceData) {
+        let key = DataKey::PriceHistory(asset_code);
+        let mut history: Vec<HistoricalPrice> = env.storage().persistent().get(&key).unwrap_or(Vec::new(env));
+
+        if let Some(last) = history.last() {
+            if data.timestamp <= last.timestamp {
+                return;
+            }
+        }
+
+        history.push_back(HistoricalPrice {
+            price: data.price,
+            timestamp: data.timestamp,
+            volume: data.volume_24h,
+            confidence: data.confidence,
+        });
+        if history.len() > MAX_CACHED_PRICES {
+            history.pop_front();
+        }
+        env.storage().persistent().set(&key, &history);
+    }
+
+    /// Helper function to compute a TWAP from the most recent cached prices. Each price is
+    /// weighted by how long it stood: until the next cached price, or until now for the latest.
+    /// Prices older than the TWAP age limit are left out.
+    fn cached_twap(env: &Env, asset_code: String) -> Result<i128, OracleError> {
+        let history: Vec<HistoricalPrice> = env.storage()
+            .persistent()
+            .get(&DataKey::PriceHistory(asset_code))
+            .unwrap_or(Vec::new(env));
+        let latest = history.last().ok_or(OracleError::ContractCallFailed)?;
+
+        let points: u32 = env.storage().instance().get(&DataKey::TwapFallbackPoints).unwrap_or(DEFAULT_TWAP_FALLBACK_POINTS);
+        let start = history.len().saturating_sub(points);
+        let now = env.ledger().timestamp();
+
+        let twap_max_age: u64 = env.storage().instance().get(&DataKey::TwapMaxAge).unwrap_or(0);
+        if twap_max_age > 0 && now.saturating_sub(latest.timestamp) > twap_max_age {
+            return Err(OracleError::StalePrice);
+        }
+        let oldest_allowed = if twap_max_age > 0 { now.saturating_sub(twap_max_age) } else { 0 };
+
+        let mut weighted_sum: i128 = 0;
+        let mut total_duration: i128 = 0;
+        for i in start..history.len() {
+            let sample = history.get(i).unwrap();
+            if sample.timestamp < oldest_allowed {
+                continue;
+            }
+            let until = match history.get(i + 1) {
+                Some(next) => next.timestamp,
+                None => now.max(sample.timestamp),
+            };
+            let duration = (until - sample.timestamp) as i128;
+            weighted_sum += sample.price * duration;
+            total_duration += duration;
+        }
+
+        if total_duration == 0 {
+            return Ok(latest.price);
+        }
+        Ok(weighted_sum / total_duration)
+    }
+
+    /// Helper function to require authorization from the stored admin
+    fn require_admin(env: &Env) -> Result<(), OracleError> {
+        let admin: Address = env.storage()
+            .instance()
+            .get(&DataKey::Admin)
+            .ok_or(OracleError::NotInitialized)?;
+        admin.require_auth();
+        Ok(())
+    }
+
     /// Helper function to check if an asset is supported
     fn is_asset_supported(env: &Env, asset_code: String) -> bool {
         // List of supported assets
-        if asset_code == String::from_str(env, "AQUA") { true }
-        else if asset_code == String::from_str(env, "yUSDC") { true }
-        else if asset_code == String::from_str(env, "EURC") { true }
-        else if asset_code == String::from_str(env, "BTCLN") { true }
-        else if asset_code == String::from_str(env, "KALE") { true }
-        else { false }
+        ["AQUA", "yUSDC", "EURC", "BTCLN", "KALE"]
+            .iter()
+            .any(|code| asset_code == String::from_str(env, code))
     }
 
     /// Helper function to convert asset code to address
@@ -0,0 +1,75 @@
+// Test helpers shared by every contract crate that talks to the Reflector oracle
+use soroban_sdk::{contract, contractimpl, vec, Address, Env, String, Vec};
+
+use crate::{PriceData, ReflectorPriceData};
+
+// Stand-in for the Reflector contract at its hardcoded address, serving every call in
+// ReflectorPriceInterface. get_twap_price fails until a TWAP is set, so the client's local
+// fallback can be exercised. The detector reads get_price_data from the same address, so the
+// mock answers that too, from the latest price set.
+#[contract]
+pub struct MockReflector;
+
+#[contractimpl]
+impl MockReflector {
+    pub fn set_price(env: Env, data: ReflectorPriceData) {
+        let mut history: Vec<ReflectorPriceData> = env.storage().instance().get(&String::from_str(&env, "history")).unwrap_or(Vec::new(&env));
+        history.push_back(data.clone());
+        env.storage().instance().set(&String::from_str(&env, "history"), &history);
+        env.storage().instance().set(&String::from_str(&env, "price"), &data);
+    }
+
+    pub fn set_twap_price(env: Env, price: i128) {
+        env.storage().instance().set(&String::from_str(&env, "twap"), &price);
+    }
+
+    pub fn get_price(env: Env, _asset_address: Address) -> ReflectorPriceData {
+        env.storage().instance().get(&String::from_str(&env, "price")).unwrap_or(ReflectorPriceData {
+            price: 1_0000000,
+            timestamp: 12345,
+            confidence: 95,
+            volume_24h: 250000_0000000,
+        })
+    }
+
+    pub fn get_twap_price(env: Env, _asset_address: Address, _records: u32) -> i128 {
+        env.storage().instance().get(&String::from_str(&env, "twap")).expect("no TWAP set")
+    }
+
+    pub fn get_historical_prices(env: Env, _asset_address: Address, count: u32) -> Vec<ReflectorPriceData> {
+        let history: Vec<ReflectorPriceData> = env.storage().instance().get(&String::from_str(&env, "history")).unwrap_or(Vec::new(&env));
+        history.slice(history.len().saturating_sub(count)..history.len())
+    }
+
+    pub fn get_supported_assets(env: Env) -> Vec<Address> {
+        vec![&env, Address::from_string(&String::from_str(&env, "GBNZILSTVQZ4R7IKQDGHYGY2QXL5QOFJYQMXPKWRRM5PAV7Y4M67AQUA"))]
+    }
+
+    pub fn get_oracle_decimals(_env: Env) -> u32 {
+        14
+    }
+
+    pub fn get_price_change_percentage(_env: Env, _asset_address: Address) -> i128 {
+        0
+    }
+
+    pub fn get_price_data(env: Env, asset_code: String) -> PriceData {
+        let data = Self::get_price(env.clone(), env.current_contract_address());
+        PriceData {
+            asset: asset_code,
+            price: data.price,
+            volume_24h: data.volume_24h,
+            timestamp: data.timestamp,
+            source: String::from_str(&env, "Reflector"),
+            confidence: data.confidence as i128,
+            price_change_percentage: 0,
+        }
+    }
+}
+
+/// Register a MockReflector at the Reflector address the contracts call
+pub fn register_mock_reflector(env: &Env) -> MockReflectorClient<'_> {
+    let reflector_id = Address::from_string(&String::from_str(env, "CBIW2BTCOMOEV5WQC2JRWVH4TAXCZNAUIUOXYVAYP4YDW4D3AEEQPNTC"));
+    env.register_at(&reflector_id, MockReflector, ());
+    MockReflectorClient::new(env, &reflector_id)
+}
@@ -5,6 +5,15 @@
 
 use soroban_sdk::{contract, contractclient, contractimpl, contracttype, contracterror, Env, String, Address, Vec};
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub enum OrderTimeInForce {
+    IOC, // Immediate-or-cancel: fill what is available, cancel the rest
+    FOK, // Fill-or-kill: fill the whole order or fail
+    GTC, // Good-till-cancelled: keep the unfilled remainder as a pending order
+}
+
 #[derive(Clone)]
 #[contracttype]
 pub struct TradeOrder {
@@ -13,18 +22,23 @@ pub struct TradeOrder {
     pub amount: i64,
     pub price_limit: i64, // Maximum buy price or minimum sell price
     pub order_type: String, // "buy" or "sell"
+    pub execution_type: String, // "market" or "limit"
+    pub time_in_force: OrderTimeInForce,
     pub deadline: u64,
     pub trader: Address,
 }
 
 #[contracttype]
+#[derive(Clone, Debug, PartialEq)]
 pub struct TradeResult {
     pub success: bool,
     pub executed_amount: i64,
     pub average_price: i64,
+    pub slippage_bps: i64, // Execution price vs the pre-trade oracle price; positive is worse for the trader
     pub fees_paid: i64,
     pub timestamp: u64,
     pub error_message: String,
+    pub pending_order_id: Option<u64>, // Set when a GTC remainder was left on the book
 }
 
 #[contracttype]
@@ -35,7 +49,7 @@ pub struct BatchTradeParameters {
 }
 
 #[contracterror]
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TradingError {
     InsufficientBalance = 1,
     PriceLimitExceeded = 2,
@@ -44,8 +58,38 @@ pub enum TradingError {
     InsufficientLiquidity = 5,
     SlippageTooHigh = 6,
     InvalidOrderType = 7,
+    AlreadyInitialized = 8,
+    NotInitialized = 9,
+    InvalidDeadline = 10,
+    BatchTooLarge = 11,
 }
 
+#[contracttype]
+#[derive(Clone)]
+pub enum DataKey {
+    Admin,
+    PriceOracle,
+    MaxLimitDeviationBps,
+    DeadlineBuffers,
+    NextOrderId,
+    PendingOrder(u64),
+    MaxBatchSize,
+    RealizedSlippage(Address),
+}
+
+// Reflector price record, as returned by the oracle's get_price
+#[contracttype]
+#[derive(Clone)]
+pub struct OraclePriceData {
+    pub price: i128,
+    pub timestamp: u64,
+    pub confidence: u32,
+    pub volume_24h: i128,
+}
+
+// Default cap on orders per batch, keeping batches well inside per-transaction resource limits
+const DEFAULT_MAX_BATCH_SIZE: u32 = 10;
+
 // Interface for a standard DEX contract
 #[contractclient(name = "DexClient")]
 pub trait Dex {
@@ -59,12 +103,60 @@ pub trait Dex {
     ) -> Vec<i64>;
 }
 
+// Interface for the Reflector price oracle
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    fn get_price(env: Env, asset_address: Address) -> OraclePriceData;
+    fn get_oracle_decimals(env: Env) -> u32;
+}
+
 #[contract]
 pub struct TradingEngine;
 
 #[contractimpl]
 impl TradingEngine {
+    /// Initialize the engine with an admin allowed to tune trading settings
+    pub fn initialize(env: Env, admin: Address) -> Result<(), TradingError> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(TradingError::AlreadyInitialized);
+        }
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        Ok(())
+    }
+
+    /// Set the oracle used to sanity-check order limits (admin only)
+    pub fn set_price_oracle(env: Env, oracle: Address) -> Result<(), TradingError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::PriceOracle, &oracle);
+        Ok(())
+    }
+
+    /// Set how far, in basis points, an order's limit may sit from the oracle price (admin only)
+    pub fn set_max_limit_deviation_bps(env: Env, max_limit_deviation_bps: i128) -> Result<(), TradingError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::MaxLimitDeviationBps, &max_limit_deviation_bps);
+        Ok(())
+    }
+
+    /// Set the window, in seconds from now, that deadlines must fall into (admin only)
+    pub fn set_deadline_buffers(env: Env, min_deadline_buffer: u64, max_deadline_buffer: u64) -> Result<(), TradingError> {
+        Self::require_admin(&env)?;
+        if min_deadline_buffer > max_deadline_buffer {
+            return Err(TradingError::InvalidDeadline);
+        }
+        env.storage().instance().set(&DataKey::DeadlineBuffers, &(min_deadline_buffer, max_deadline_buffer));
+        Ok(())
+    }
+
+    /// Set the maximum number of orders accepted in one batch (admin only)
+    pub fn set_max_batch_size(env: Env, max_batch_size: u32) -> Result<(), TradingError> {
+        Self::require_admin(&env)?;
+        env.storage().instance().set(&DataKey::MaxBatchSize, &max_batch_size);
+        Ok(())
+    }
+
     /// Executes a buy order by swapping a 'payment_asset' for a 'target_asset'.
+    #[allow(clippy::too_many_arguments)]
     pub fn execute_buy_order(
         env: Env,
         trader: Address,
@@ -77,14 +169,14 @@ impl TradingEngine {
     ) -> Result<TradeResult, TradingError> {
         trader.require_auth();
 
-        if env.ledger().timestamp() > deadline {
-            return Err(TradingError::DeadlineExceeded);
-        }
+        Self::validate_deadline(&env, deadline)?;
+
+        let oracle_price = Self::get_oracle_price(&env, &target_asset);
 
         let dex_client = DexClient::new(&env, &dex_contract);
         let mut path = Vec::new(&env);
         path.push_back(payment_asset);
-        path.push_back(target_asset);
+        path.push_back(target_asset.clone());
 
         let amounts = dex_client.swap_exact_tokens_for_tokens(
             &trader.clone(),
@@ -101,17 +193,23 @@ impl TradingEngine {
             return Err(TradingError::SlippageTooHigh);
         }
 
+        let slippage_bps = Self::slippage_bps(oracle_price, amount_paid, amount_received, true);
+        Self::record_realized_slippage(&env, &target_asset, oracle_price, slippage_bps);
+
         Ok(TradeResult {
             success: true,
             executed_amount: amount_received,
             average_price: amount_paid / amount_received, // Simplified price
+            slippage_bps,
             fees_paid: 0, // The DEX handles fees internally
             timestamp: env.ledger().timestamp(),
             error_message: String::from_str(&env, ""),
+            pending_order_id: None,
         })
     }
 
     /// Executes a sell order by swapping a 'target_asset' for a 'payment_asset'.
+    #[allow(clippy::too_many_arguments)]
     pub fn execute_sell_order(
         env: Env,
         trader: Address,
@@ -124,13 +222,13 @@ impl TradingEngine {
     ) -> Result<TradeResult, TradingError> {
         trader.require_auth();
 
-        if env.ledger().timestamp() > deadline {
-            return Err(TradingError::DeadlineExceeded);
-        }
+        Self::validate_deadline(&env, deadline)?;
+
+        let oracle_price = Self::get_oracle_price(&env, &target_asset);
 
         let dex_client = DexClient::new(&env, &dex_contract);
         let mut path = Vec::new(&env);
-        path.push_back(target_asset);
+        path.push_back(target_asset.clone());
         path.push_back(payment_asset);
 
         let amounts = dex_client.swap_exact_tokens_for_tokens(
@@ -148,17 +246,23 @@ impl TradingEngine {
             return Err(TradingError::SlippageTooHigh);
         }
 
+        let slippage_bps = Self::slippage_bps(oracle_price, amount_received, amount_sold, false);
+        Self::record_realized_slippage(&env, &target_asset, oracle_price, slippage_bps);
+
         Ok(TradeResult {
             success: true,
             executed_amount: amount_sold,
             average_price: amount_received / amount_sold, // Simplified price
+            slippage_bps,
             fees_paid: 0, // The DEX handles fees internally
             timestamp: env.ledger().timestamp(),
             error_message: String::from_str(&env, ""),
+            pending_order_id: None,
         })
     }
 
-    /// Execute multiple trades atomically.
+    /// Execute multiple trades atomically. The batch fails if any order's slippage against
+    /// the oracle exceeds `max_slippage_bps`; without an oracle there is nothing to measure.
     pub fn batch_execute_trades(
         env: Env,
         params: BatchTradeParameters,
@@ -166,66 +270,365 @@ impl TradingEngine {
     ) -> Result<Vec<TradeResult>, TradingError> {
         trader.require_auth();
 
-        if env.ledger().timestamp() > params.deadline {
-            return Err(TradingError::DeadlineExceeded);
-        }
+        Self::validate_batch_limits(&env, &params)?;
 
         let mut results = Vec::new(&env);
 
         for order in params.orders.iter() {
-            let dex_contract = env.storage().persistent().get(&order.exchange).unwrap();
-
-            let buy_order = String::from_str(&env, "buy");
-            let sell_order = String::from_str(&env, "sell");
-
-            let result = if order.order_type == buy_order {
-                Self::execute_buy_order(
-                    env.clone(),
-                    trader.clone(),
-                    dex_contract,
-                    env.storage().persistent().get(&String::from_str(&env, "YUSDC")).unwrap(), // payment_asset
-                    order.asset, // target_asset
-                    order.amount,
-                    order.price_limit, // Interpreted as max_payment_amount
-                    order.deadline,
-                )
-            } else if order.order_type == sell_order {
-                Self::execute_sell_order(
-                    env.clone(),
-                    trader.clone(),
-                    dex_contract,
-                    order.asset, // target_asset
-                    env.storage().persistent().get(&String::from_str(&env, "YUSDC")).unwrap(), // payment_asset
-                    order.amount,
-                    order.price_limit, // Interpreted as min_payment_amount
-                    order.deadline,
-                )
-            } else {
-                return Err(TradingError::InvalidOrderType);
+            let order = TradeOrder {
+                trader: trader.clone(),
+                ..order
             };
-
-            match result {
-                Ok(trade_result) => results.push_back(trade_result),
-                Err(e) => return Err(e),
+            Self::check_limit_against_oracle(&env, &order)?;
+            let result = Self::execute_order(&env, order, None)?;
+            if result.slippage_bps > params.max_slippage_bps {
+                return Err(TradingError::SlippageTooHigh);
             }
+            results.push_back(result);
         }
 
         Ok(results)
     }
+
+    /// Dry-run a batch without executing it, returning whether each order would pass
+    /// validation: live deadline, registered exchange, known order type and a sane limit.
+    /// Batch-wide problems, such as an oversized batch or a bad batch deadline, are returned as errors.
+    pub fn validate_batch(env: Env, params: BatchTradeParameters) -> Result<Vec<bool>, TradingError> {
+        Self::validate_batch_limits(&env, &params)?;
+
+        let mut validity = Vec::new(&env);
+        for order in params.orders.iter() {
+            validity.push_back(Self::validate_order(&env, &order).is_ok());
+        }
+        Ok(validity)
+    }
+
+    /// Retry the unfilled remainder of a good-till-cancelled order
+    pub fn execute_pending_order(env: Env, order_id: u64) -> Result<TradeResult, TradingError> {
+        let order = Self::get_pending_order(env.clone(), order_id).ok_or(TradingError::InvalidOrderType)?;
+        order.trader.require_auth();
+
+        Self::execute_order(&env, order, Some(order_id))
+    }
+
+    /// Cancel a pending good-till-cancelled order owned by `trader`
+    pub fn cancel_order(env: Env, order_id: u64, trader: Address) -> Result<(), TradingError> {
+        trader.require_auth();
+
+        match Self::get_pending_order(env.clone(), order_id) {
+            Some(order) if order.trader == trader => {
+                env.storage().persistent().remove(&DataKey::PendingOrder(order_id));
+                Ok(())
+            }
+            _ => Err(TradingError::InvalidOrderType),
+        }
+    }
+
+    /// Get a pending good-till-cancelled order by id
+    pub fn get_pending_order(env: Env, order_id: u64) -> Option<TradeOrder> {
+        env.storage().persistent().get(&DataKey::PendingOrder(order_id))
+    }
+
+    /// Realized slippage of the most recent trade in `asset` against the oracle price, in basis
+    /// points. Zero until a trade in the asset has executed with an oracle configured.
+    pub fn get_realized_slippage(env: Env, asset: Address) -> i128 {
+        env.storage().persistent().get(&DataKey::RealizedSlippage(asset)).unwrap_or(0)
+    }
+
+    /// Execute a single order against its registered exchange, honouring its time-in-force.
+    /// `pending_id` is set when the order is a stored GTC remainder being retried.
+    fn execute_order(env: &Env, order: TradeOrder, pending_id: Option<u64>) -> Result<TradeResult, TradingError> {
+        Self::validate_deadline(env, order.deadline)?;
+
+        let dex_contract: Address = env.storage().persistent().get(&order.exchange).ok_or(TradingError::ExchangeUnavailable)?;
+        let payment_asset: Address = env.storage().persistent().get(&String::from_str(env, "YUSDC")).ok_or(TradingError::ExchangeUnavailable)?;
+
+        let is_buy = if order.order_type == String::from_str(env, "buy") {
+            true
+        } else if order.order_type == String::from_str(env, "sell") {
+            false
+        } else {
+            return Err(TradingError::InvalidOrderType);
+        };
+
+        let is_limit = if order.execution_type == String::from_str(env, "limit") {
+            true
+        } else if order.execution_type == String::from_str(env, "market") {
+            false
+        } else {
+            return Err(TradingError::InvalidOrderType);
+        };
+
+        // A limit order the market hasn't reached yet rests as a GTC order until it does
+        if is_limit && !Self::limit_reached(env, &order, is_buy) {
+            let order_id = match pending_id {
+                Some(order_id) => order_id,
+                None => Self::next_order_id(env),
+            };
+            let resting = TradeOrder {
+                time_in_force: OrderTimeInForce::GTC,
+                ..order
+            };
+            env.storage().persistent().set(&DataKey::PendingOrder(order_id), &resting);
+
+            return Ok(TradeResult {
+                success: true,
+                executed_amount: 0,
+                average_price: 0,
+                slippage_bps: 0,
+                fees_paid: 0,
+                timestamp: env.ledger().timestamp(),
+                error_message: String::from_str(env, ""),
+                pending_order_id: Some(order_id),
+            });
+        }
+
+        // Only fill-or-kill orders ask the DEX for the full size; the others accept partial fills
+        let fill_or_kill = order.time_in_force == OrderTimeInForce::FOK;
+        let mut path = Vec::new(env);
+        let (amount_in, amount_out_min) = if is_buy {
+            path.push_back(payment_asset);
+            path.push_back(order.asset.clone());
+            (order.price_limit, if fill_or_kill { order.amount } else { 0 })
+        } else {
+            path.push_back(order.asset.clone());
+            path.push_back(payment_asset);
+            (order.amount, if fill_or_kill { order.price_limit } else { 0 })
+        };
+
+        let oracle_price = Self::get_oracle_price(env, &order.asset);
+
+        let dex_client = DexClient::new(env, &dex_contract);
+        let amounts = dex_client.swap_exact_tokens_for_tokens(
+            &order.trader,
+            &amount_in,
+            &amount_out_min,
+            &path,
+            &order.deadline,
+        );
+        let amount_given = amounts.get(0).unwrap_or(0);
+        let amount_received = amounts.get(1).unwrap_or(0);
+
+        // Buys are sized by the asset received, sells by the asset given up
+        let (filled, payment) = if is_buy {
+            (amount_received, amount_given)
+        } else {
+            (amount_given, amount_received)
+        };
+
+        if filled <= 0 || (fill_or_kill && filled < order.amount) {
+            return Err(TradingError::SlippageTooHigh);
+        }
+
+        // A partial fill must still respect the order's limit pro rata
+        let payment_at_limit = order.price_limit as i128 * filled as i128;
+        let within_limit = if is_buy {
+            payment as i128 * order.amount as i128 <= payment_at_limit
+        } else {
+            payment as i128 * order.amount as i128 >= payment_at_limit
+        };
+        if !within_limit {
+            return Err(TradingError::PriceLimitExceeded);
+        }
+
+        if let Some(order_id) = pending_id {
+            env.storage().persistent().remove(&DataKey::PendingOrder(order_id));
+        }
+
+        let slippage_bps = Self::slippage_bps(oracle_price, payment, filled, is_buy);
+        Self::record_realized_slippage(env, &order.asset, oracle_price, slippage_bps);
+
+        // Keep the unfilled remainder of a GTC order; IOC remainders are simply dropped
+        let mut pending_order_id = None;
+        let remaining = order.amount - filled;
+        if remaining > 0 && order.time_in_force == OrderTimeInForce::GTC {
+            let order_id = match pending_id {
+                Some(order_id) => order_id,
+                None => Self::next_order_id(env),
+            };
+            let remainder = TradeOrder {
+                amount: remaining,
+                price_limit: (order.price_limit as i128 * remaining as i128 / order.amount as i128) as i64,
+                ..order
+            };
+            env.storage().persistent().set(&DataKey::PendingOrder(order_id), &remainder);
+            pending_order_id = Some(order_id);
+        }
+
+        Ok(TradeResult {
+            success: true,
+            executed_amount: filled,
+            average_price: payment / filled, // Simplified price
+            slippage_bps,
+            fees_paid: 0, // The DEX handles fees internally
+            timestamp: env.ledger().timestamp(),
+            error_message: String::from_str(env, ""),
+            pending_order_id,
+        })
+    }
+
+    /// Reject oversized batches before any order runs, rather than failing partway through,
+    /// and batch deadlines outside the allowed window
+    fn validate_batch_limits(env: &Env, params: &BatchTradeParameters) -> Result<(), TradingError> {
+        let max_batch_size: u32 = env.storage().instance().get(&DataKey::MaxBatchSize).unwrap_or(DEFAULT_MAX_BATCH_SIZE);
+        if params.orders.len() > max_batch_size {
+            return Err(TradingError::BatchTooLarge);
+        }
+
+        Self::validate_deadline(env, params.deadline)
+    }
+
+    /// Check everything about an order that can be known before executing it
+    fn validate_order(env: &Env, order: &TradeOrder) -> Result<(), TradingError> {
+        Self::validate_deadline(env, order.deadline)?;
+
+        if !env.storage().persistent().has(&order.exchange) {
+            return Err(TradingError::ExchangeUnavailable);
+        }
+
+        if order.order_type != String::from_str(env, "buy") && order.order_type != String::from_str(env, "sell") {
+            return Err(TradingError::InvalidOrderType);
+        }
+
+        if order.execution_type != String::from_str(env, "market") && order.execution_type != String::from_str(env, "limit") {
+            return Err(TradingError::InvalidOrderType);
+        }
+
+        Self::check_limit_against_oracle(env, order)
+    }
+
+    /// Reject orders whose limit is too far from the oracle's view of the market.
+    /// Skipped until both an oracle and a maximum deviation are configured.
+    fn check_limit_against_oracle(env: &Env, order: &TradeOrder) -> Result<(), TradingError> {
+        let max_deviation_bps: i128 = match env.storage().instance().get(&DataKey::MaxLimitDeviationBps) {
+            Some(max_deviation_bps) => max_deviation_bps,
+            None => return Ok(()),
+        };
+        let (oracle_price, price_scale) = match Self::get_oracle_price(env, &order.asset) {
+            Some(quote) => quote,
+            None => return Ok(()),
+        };
+
+        // price_limit is a total payment, so compare it against the order's notional at the oracle price
+        let market_value = order.amount as i128 * oracle_price / price_scale;
+        if market_value <= 0 {
+            return Err(TradingError::PriceLimitExceeded);
+        }
+
+        let deviation_bps = (order.price_limit as i128 - market_value).abs() * 10000 / market_value;
+        if deviation_bps > max_deviation_bps {
+            return Err(TradingError::PriceLimitExceeded);
+        }
+        Ok(())
+    }
+
+    /// Whether the oracle price has reached a limit order's price: at or below the limit for
+    /// a buy, at or above it for a sell. Without an oracle the DEX's own bounds enforce the limit.
+    fn limit_reached(env: &Env, order: &TradeOrder, is_buy: bool) -> bool {
+        let (oracle_price, price_scale) = match Self::get_oracle_price(env, &order.asset) {
+            Some(quote) => quote,
+            None => return true,
+        };
+
+        // price_limit is a total payment, so compare it against the order's notional at the oracle price
+        let market_value = order.amount as i128 * oracle_price / price_scale;
+        if is_buy {
+            market_value <= order.price_limit as i128
+        } else {
+            market_value >= order.price_limit as i128
+        }
+    }
+
+    /// Reject expired deadlines, and deadlines outside the configured buffer window.
+    /// Deadlines too close to now can't realistically settle; ones too far out invite replays.
+    fn validate_deadline(env: &Env, deadline: u64) -> Result<(), TradingError> {
+        let now = env.ledger().timestamp();
+        if now > deadline {
+            return Err(TradingError::DeadlineExceeded);
+        }
+
+        let buffers: Option<(u64, u64)> = env.storage().instance().get(&DataKey::DeadlineBuffers);
+        if let Some((min_deadline_buffer, max_deadline_buffer)) = buffers {
+            if deadline < now.saturating_add(min_deadline_buffer) || deadline > now.saturating_add(max_deadline_buffer) {
+                return Err(TradingError::InvalidDeadline);
+            }
+        }
+        Ok(())
+    }
+
+    /// Current oracle price for an asset with its scale (10^decimals), if an oracle is configured
+    /// and answers. A failing oracle only costs the slippage report and limit checks, never the trade itself.
+    fn get_oracle_price(env: &Env, asset: &Address) -> Option<(i128, i128)> {
+        let oracle: Address = env.storage().instance().get(&DataKey::PriceOracle)?;
+        let oracle_client = PriceOracleClient::new(env, &oracle);
+        let price = match oracle_client.try_get_price(asset) {
+            Ok(Ok(data)) => data.price,
+            _ => return None,
+        };
+        let price_scale = match oracle_client.try_get_oracle_decimals() {
+            Ok(Ok(decimals)) => 10i128.checked_pow(decimals)?,
+            _ => return None,
+        };
+        Some((price, price_scale))
+    }
+
+    /// Slippage of an execution against the oracle price, in basis points.
+    /// Positive means the trader paid more (buy) or received less (sell) than the oracle price.
+    fn slippage_bps(oracle_price: Option<(i128, i128)>, payment: i64, filled: i64, is_buy: bool) -> i64 {
+        let (oracle_price, price_scale) = match oracle_price {
+            Some((oracle_price, price_scale)) if oracle_price > 0 && filled > 0 => (oracle_price, price_scale),
+            _ => return 0,
+        };
+
+        // Express the execution price in the oracle's decimals
+        let execution_price = payment as i128 * price_scale / filled as i128;
+        let deviation = if is_buy {
+            execution_price - oracle_price
+        } else {
+            oracle_price - execution_price
+        };
+        (deviation * 10000 / oracle_price) as i64
+    }
+
+    /// Store a trade's slippage for the asset. Without an oracle price there is nothing to
+    /// measure against, so the last recorded value is kept.
+    fn record_realized_slippage(env: &Env, asset: &Address, oracle_price: Option<(i128, i128)>, slippage_bps: i64) {
+        if oracle_price.is_some() {
+            env.storage().persistent().set(&DataKey::RealizedSlippage(asset.clone()), &(slippage_bps as i128));
+        }
+    }
+
+    /// Require authorization from the stored admin
+    fn require_admin(env: &Env) -> Result<(), TradingError> {
+        let admin: Address = env.storage()
+            .instance()
+            .get(&DataKey::Admin)
+            .ok_or(TradingError::NotInitialized)?;
+        admin.require_auth();
+        Ok(())
+    }
+
+    /// Allocate the next pending order id
+    fn next_order_id(env: &Env) -> u64 {
+        let order_id: u64 = env.storage().persistent().get(&DataKey::NextOrderId).unwrap_or(0) + 1;
+        env.storage().persistent().set(&DataKey::NextOrderId, &order_id);
+        order_id
+    }
 }
 
 #[cfg(test)]
 mod test_trading_engine {
     use super::*;
     use soroban_sdk::{Env, String, Address, Vec, testutils::{Address as _, Ledger as _}};
+    use reflector_oracle_client::{ReflectorPriceData, testutils::register_mock_reflector};
 
     // Mock DEX contract for testing
     #[contract]
     pub struct MockDex;
 
     #[contractimpl]
-    impl Dex for MockDex {
-        fn swap_exact_tokens_for_tokens(
+    impl MockDex {
+        pub fn swap_exact_tokens_for_tokens(
             _env: Env,
             _trader: Address,
             amount_in: i64,
@@ -240,35 +643,104 @@ mod test_trading_engine {
         }
     }
 
+    // Mock DEX that can only fill up to a fixed depth, at a 1:1 price. Contracts exporting the
+    // same function need their own module.
+    mod partial_fill_dex {
+        use soroban_sdk::{contract, contractimpl, Address, Env, Vec};
+
+        #[contract]
+        pub struct PartialFillDex;
+
+        #[contractimpl]
+        impl PartialFillDex {
+            pub fn swap_exact_tokens_for_tokens(
+                env: Env,
+                _trader: Address,
+                amount_in: i64,
+                _amount_out_min: i64,
+                _path: Vec<Address>,
+                _deadline: u64,
+            ) -> Vec<i64> {
+                let filled = amount_in.min(50_0000000);
+                let mut amounts = Vec::new(&env);
+                amounts.push_back(filled);
+                amounts.push_back(filled);
+                amounts
+            }
+        }
+    }
+    use partial_fill_dex::PartialFillDex;
+
+    // Register the shared Reflector mock quoting every asset at 1.0, in its 14 decimals
+    fn register_oracle(env: &Env) -> Address {
+        let reflector = register_mock_reflector(env);
+        reflector.set_price(&ReflectorPriceData {
+            price: 1_00000000000000,
+            timestamp: env.ledger().timestamp(),
+            confidence: 95,
+            volume_24h: 1000000_0000000,
+        });
+        reflector.address
+    }
+
     fn setup_test<'a>() -> (Env, TradingEngineClient<'a>, Address, Address, Address, Address) {
         let env = Env::default();
+        env.mock_all_auths();
         env.ledger().with_mut(|li| {
             li.timestamp = 12345;
         });
 
-        let contract_id = env.register_contract(None, TradingEngine);
+        let contract_id = env.register(TradingEngine, ());
         let client = TradingEngineClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
 
-        let trader = Address::random(&env);
-        let dex_contract = env.register_contract(None, MockDex);
+        let trader = Address::generate(&env);
+        let dex_contract = env.register(MockDex, ());
+        let partial_dex = env.register(PartialFillDex, ());
         let payment_asset = Address::from_string(&String::from_str(&env, "CABWYQLGOQ5Y3RIYUVYJZVA355YVX4SPAMN6ORDAVJZQBPPHLHRRLNMS"));
         let target_asset = Address::from_string(&String::from_str(&env, "CDJF2JQINO7WRFXB2AAHLONFDPPI4M3W2UM5THGQQ7JMJDIEJYC4CMPG"));
 
-        env.storage().persistent().set(&String::from_str(&env, "stellar_dex"), &dex_contract);
-        env.storage().persistent().set(&String::from_str(&env, "YUSDC"), &payment_asset);
+        env.as_contract(&contract_id, || {
+            env.storage().persistent().set(&String::from_str(&env, "stellar_dex"), &dex_contract);
+            env.storage().persistent().set(&String::from_str(&env, "partial_dex"), &partial_dex);
+            env.storage().persistent().set(&String::from_str(&env, "YUSDC"), &payment_asset);
+        });
 
         (env, client, trader, dex_contract, payment_asset, target_asset)
     }
 
+    fn partial_fill_batch(env: &Env, trader: &Address, target_asset: &Address, time_in_force: OrderTimeInForce) -> BatchTradeParameters {
+        let deadline = env.ledger().timestamp() + 100;
+        let mut orders = Vec::new(env);
+        orders.push_back(TradeOrder {
+            asset: target_asset.clone(),
+            exchange: String::from_str(env, "partial_dex"),
+            amount: 100_0000000,
+            price_limit: 101_0000000,
+            order_type: String::from_str(env, "buy"),
+            execution_type: String::from_str(env, "market"),
+            time_in_force,
+            deadline,
+            trader: trader.clone(),
+        });
+
+        BatchTradeParameters {
+            orders,
+            max_slippage_bps: 100,
+            deadline,
+        }
+    }
+
     #[test]
     fn test_execute_buy_order() {
         let (env, client, trader, dex_contract, payment_asset, target_asset) = setup_test();
 
-        let amount_to_buy = 100_0000000; // 100 units
+        let amount_to_buy = 99_0000000; // 99 units
         let max_payment_amount = 101_0000000; // 101 units
         let deadline = env.ledger().timestamp() + 100;
 
-        let result = client.execute_buy_order(
+        // The mock DEX returns 99% of the 101 paid, 99.99 units
+        let trade_result = client.execute_buy_order(
             &trader,
             &dex_contract,
             &payment_asset,
@@ -278,8 +750,6 @@ mod test_trading_engine {
             &deadline,
         );
 
-        assert!(result.is_ok());
-        let trade_result = result.unwrap();
         assert!(trade_result.success);
         assert_eq!(trade_result.executed_amount, max_payment_amount * 99 / 100);
     }
@@ -292,7 +762,7 @@ mod test_trading_engine {
         let min_payment_amount = 99_0000000; // 99 units
         let deadline = env.ledger().timestamp() + 100;
 
-        let result = client.execute_sell_order(
+        let trade_result = client.execute_sell_order(
             &trader,
             &dex_contract,
             &target_asset,
@@ -302,8 +772,6 @@ mod test_trading_engine {
             &deadline,
         );
 
-        assert!(result.is_ok());
-        let trade_result = result.unwrap();
         assert!(trade_result.success);
         assert_eq!(trade_result.executed_amount, amount_to_sell);
     }
@@ -328,4 +796,300 @@ mod test_trading_engine {
 
         assert_eq!(result, Err(Ok(TradingError::DeadlineExceeded)));
     }
+
+    #[test]
+    fn test_fok_order_fails_on_partial_fill() {
+        let (env, client, trader, _dex_contract, _payment_asset, target_asset) = setup_test();
+
+        let params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::FOK);
+        let result = client.try_batch_execute_trades(&params, &trader);
+
+        assert_eq!(result, Err(Ok(TradingError::SlippageTooHigh)));
+    }
+
+    #[test]
+    fn test_ioc_order_cancels_remainder() {
+        let (env, client, trader, _dex_contract, _payment_asset, target_asset) = setup_test();
+
+        let params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::IOC);
+        let results = client.batch_execute_trades(&params, &trader);
+
+        let trade_result = results.get(0).unwrap();
+        assert_eq!(trade_result.executed_amount, 50_0000000);
+        assert_eq!(trade_result.pending_order_id, None);
+        assert!(client.get_pending_order(&1).is_none());
+    }
+
+    #[test]
+    fn test_gtc_order_keeps_remainder() {
+        let (env, client, trader, _dex_contract, _payment_asset, target_asset) = setup_test();
+
+        let params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::GTC);
+        let results = client.batch_execute_trades(&params, &trader);
+
+        let trade_result = results.get(0).unwrap();
+        assert_eq!(trade_result.executed_amount, 50_0000000);
+        assert_eq!(trade_result.pending_order_id, Some(1));
+
+        let pending = client.get_pending_order(&1).unwrap();
+        assert_eq!(pending.amount, 50_0000000);
+        assert_eq!(pending.price_limit, 50_5000000);
+        assert_eq!(pending.trader, trader);
+
+        // Retrying fills the remainder and clears the pending order
+        let retry = client.execute_pending_order(&1);
+        assert_eq!(retry.executed_amount, 50_0000000);
+        assert_eq!(retry.pending_order_id, None);
+        assert!(client.get_pending_order(&1).is_none());
+    }
+
+    #[test]
+    fn test_off_market_limit_rejected() {
+        let (env, client, trader, _dex_contract, _payment_asset, target_asset) = setup_test();
+
+        client.set_price_oracle(&register_oracle(&env));
+        client.set_max_limit_deviation_bps(&1000);
+
+        // Offering 50 units of payment for 100 units at a 1.0 oracle price is 50% off-market
+        let mut params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::IOC);
+        let mut order = params.orders.get(0).unwrap();
+        order.price_limit = 50_0000000;
+        params.orders.set(0, order);
+
+        let result = client.try_batch_execute_trades(&params, &trader);
+        assert_eq!(result, Err(Ok(TradingError::PriceLimitExceeded)));
+
+        // The original 1% over-market limit is still accepted
+        let params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::IOC);
+        assert!(client.try_batch_execute_trades(&params, &trader).is_ok());
+    }
+
+    #[test]
+    fn test_deadline_too_near() {
+        let (env, client, trader, dex_contract, payment_asset, target_asset) = setup_test();
+        client.set_deadline_buffers(&10, &3600);
+
+        let result = client.try_execute_buy_order(
+            &trader,
+            &dex_contract,
+            &payment_asset,
+            &target_asset,
+            &100_0000000,
+            &101_0000000,
+            &(env.ledger().timestamp() + 5),
+        );
+
+        assert_eq!(result, Err(Ok(TradingError::InvalidDeadline)));
+    }
+
+    #[test]
+    fn test_deadline_too_far() {
+        let (env, client, trader, dex_contract, payment_asset, target_asset) = setup_test();
+        client.set_deadline_buffers(&10, &3600);
+
+        let result = client.try_execute_sell_order(
+            &trader,
+            &dex_contract,
+            &target_asset,
+            &payment_asset,
+            &100_0000000,
+            &99_0000000,
+            &(env.ledger().timestamp() + 7200),
+        );
+
+        assert_eq!(result, Err(Ok(TradingError::InvalidDeadline)));
+    }
+
+    #[test]
+    fn test_batch_order_deadlines_checked_against_buffers() {
+        let (env, client, trader, _dex_contract, _payment_asset, target_asset) = setup_test();
+        client.set_deadline_buffers(&10, &3600);
+
+        // The batch deadline is inside the window but the order's own deadline is not
+        let mut params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::IOC);
+        params.orders.set(0, TradeOrder {
+            deadline: env.ledger().timestamp() + 7200,
+            ..params.orders.get(0).unwrap()
+        });
+
+        assert_eq!(client.validate_batch(&params), Vec::from_array(&env, [false]));
+        assert_eq!(client.try_batch_execute_trades(&params, &trader), Err(Ok(TradingError::InvalidDeadline)));
+
+        // An unbounded maximum buffer saturates instead of overflowing
+        client.set_deadline_buffers(&10, &u64::MAX);
+        assert_eq!(client.validate_batch(&params), Vec::from_array(&env, [true]));
+    }
+
+    #[test]
+    fn test_cancel_gtc_order() {
+        let (env, client, trader, _dex_contract, _payment_asset, target_asset) = setup_test();
+
+        let params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::GTC);
+        client.batch_execute_trades(&params, &trader);
+        assert!(client.get_pending_order(&1).is_some());
+
+        // Only the owner can cancel
+        let other_trader = Address::generate(&env);
+        assert_eq!(client.try_cancel_order(&1, &other_trader), Err(Ok(TradingError::InvalidOrderType)));
+
+        client.cancel_order(&1, &trader);
+        assert!(client.get_pending_order(&1).is_none());
+        assert_eq!(client.try_execute_pending_order(&1), Err(Ok(TradingError::InvalidOrderType)));
+        assert_eq!(client.try_cancel_order(&1, &trader), Err(Ok(TradingError::InvalidOrderType)));
+    }
+
+    #[test]
+    fn test_trade_result_reports_slippage_and_fees() {
+        let (env, client, trader, dex_contract, payment_asset, target_asset) = setup_test();
+        client.set_price_oracle(&register_oracle(&env));
+
+        // The mock DEX returns 99 for 100 sold at a 1.0 oracle price
+        let trade_result = client.execute_sell_order(
+            &trader,
+            &dex_contract,
+            &target_asset,
+            &payment_asset,
+            &100_0000000,
+            &99_0000000,
+            &(env.ledger().timestamp() + 100),
+        );
+
+        assert_eq!(trade_result.slippage_bps, 100);
+        assert_eq!(trade_result.fees_paid, 0);
+    }
+
+    #[test]
+    fn test_batch_enforces_max_slippage() {
+        let (env, client, trader, _dex_contract, _payment_asset, target_asset) = setup_test();
+        client.set_price_oracle(&register_oracle(&env));
+
+        // Paying 101 for the mock DEX's 99.99 is about 101 bps over the 1.0 oracle price
+        let mut params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::IOC);
+        params.orders.set(0, TradeOrder {
+            exchange: String::from_str(&env, "stellar_dex"),
+            amount: 99_0000000,
+            ..params.orders.get(0).unwrap()
+        });
+
+        assert_eq!(client.try_batch_execute_trades(&params, &trader), Err(Ok(TradingError::SlippageTooHigh)));
+
+        params.max_slippage_bps = 150;
+        assert_eq!(client.batch_execute_trades(&params, &trader).get(0).unwrap().slippage_bps, 101);
+    }
+
+    #[test]
+    fn test_oversized_batch_rejected() {
+        let (env, client, trader, _dex_contract, _payment_asset, target_asset) = setup_test();
+        client.set_max_batch_size(&2);
+
+        let mut params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::GTC);
+        let order = params.orders.get(0).unwrap();
+        params.orders.push_back(order.clone());
+        params.orders.push_back(order);
+
+        let result = client.try_batch_execute_trades(&params, &trader);
+        assert_eq!(result, Err(Ok(TradingError::BatchTooLarge)));
+
+        // Nothing ran, so no GTC or partial-fill state was left behind
+        assert!(client.get_pending_order(&1).is_none());
+    }
+
+    #[test]
+    fn test_validate_batch_reports_each_order() {
+        let (env, client, trader, _dex_contract, _payment_asset, target_asset) = setup_test();
+
+        let mut params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::IOC);
+        let valid = params.orders.get(0).unwrap();
+        params.orders.push_back(TradeOrder {
+            exchange: String::from_str(&env, "unknown_dex"),
+            ..valid.clone()
+        });
+        params.orders.push_back(TradeOrder {
+            deadline: env.ledger().timestamp() - 1,
+            ..valid.clone()
+        });
+        params.orders.push_back(TradeOrder {
+            order_type: String::from_str(&env, "swap"),
+            ..valid
+        });
+
+        let validity = client.validate_batch(&params);
+        assert_eq!(validity, Vec::from_array(&env, [true, false, false, false]));
+
+        // Validation executes nothing
+        assert!(client.get_pending_order(&1).is_none());
+    }
+
+    #[test]
+    fn test_market_order_executes_immediately() {
+        let (env, client, trader, _dex_contract, _payment_asset, target_asset) = setup_test();
+        client.set_price_oracle(&register_oracle(&env));
+
+        let mut params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::IOC);
+        params.orders.set(0, TradeOrder {
+            amount: 40_0000000,
+            price_limit: 40_0000000,
+            ..params.orders.get(0).unwrap()
+        });
+
+        let trade_result = client.batch_execute_trades(&params, &trader).get(0).unwrap();
+        assert_eq!(trade_result.executed_amount, 40_0000000);
+        assert_eq!(trade_result.pending_order_id, None);
+    }
+
+    #[test]
+    fn test_limit_order_defers_until_reached() {
+        let (env, client, trader, _dex_contract, _payment_asset, target_asset) = setup_test();
+        client.set_price_oracle(&register_oracle(&env));
+
+        // 40 units at the oracle's 1.0 cost 40, above the 30 limit
+        let mut params = partial_fill_batch(&env, &trader, &target_asset, OrderTimeInForce::IOC);
+        params.orders.set(0, TradeOrder {
+            amount: 40_0000000,
+            price_limit: 30_0000000,
+            execution_type: String::from_str(&env, "limit"),
+            ..params.orders.get(0).unwrap()
+        });
+
+        let trade_result = client.batch_execute_trades(&params, &trader).get(0).unwrap();
+        assert_eq!(trade_result.executed_amount, 0);
+        assert_eq!(trade_result.pending_order_id, Some(1));
+
+        let pending = client.get_pending_order(&1).unwrap();
+        assert_eq!(pending.amount, 40_0000000);
+        assert_eq!(pending.time_in_force, OrderTimeInForce::GTC);
+
+        // Still out of reach, so a retry keeps resting under the same id
+        let retry = client.execute_pending_order(&1);
+        assert_eq!(retry.executed_amount, 0);
+        assert_eq!(retry.pending_order_id, Some(1));
+    }
+
+    #[test]
+    fn test_realized_slippage_recorded() {
+        let (env, client, trader, dex_contract, payment_asset, target_asset) = setup_test();
+
+        // Nothing is recorded without an oracle to measure against
+        client.execute_sell_order(&trader, &dex_contract, &target_asset, &payment_asset, &100_0000000, &99_0000000, &(env.ledger().timestamp() + 100));
+        assert_eq!(client.get_realized_slippage(&target_asset), 0);
+
+        // The mock DEX returns 99 for 100 sold at a 1.0 oracle price
+        client.set_price_oracle(&register_oracle(&env));
+        client.execute_sell_order(&trader, &dex_contract, &target_asset, &payment_asset, &100_0000000, &99_0000000, &(env.ledger().timestamp() + 100));
+        assert_eq!(client.get_realized_slippage(&target_asset), 100);
+        assert_eq!(client.get_realized_slippage(&payment_asset), 0);
+    }
+
+    #[test]
+    fn test_failing_oracle_reports_no_slippage() {
+        let (env, client, trader, dex_contract, payment_asset, target_asset) = setup_test();
+
+        // An oracle address with no contract behind it
+        client.set_price_oracle(&Address::generate(&env));
+
+        let trade_result = client.execute_sell_order(&trader, &dex_contract, &target_asset, &payment_asset, &100_0000000, &99_0000000, &(env.ledger().timestamp() + 100));
+        assert!(trade_result.success);
+        assert_eq!(trade_result.slippage_bps, 0);
+        assert_eq!(client.get_realized_slippage(&target_asset), 0);
+    }
 }